@@ -0,0 +1,95 @@
+use super::{Bsdf, BsdfSample, Frame, ThinFilm, microfacet::Ggx, face_forward, schlick_fresnel};
+use crate::util::{Color, random, vec::*};
+
+/// Glossy metal, reflections get blurrier as `roughness` goes from 0.0 to 1.0.
+/// Light is reflected by GGX microfacets, compensating for the energy
+/// single scattering loses on rough surfaces
+#[derive(Debug, Clone, Copy)]
+pub struct RoughConductor {
+    /// Reflectance at normal incidence
+    pub color: Color,
+    pub roughness: f32,
+    /// Coating whose interference colors the reflections, see [ThinFilm]
+    pub film: Option<ThinFilm>
+}
+
+impl RoughConductor {
+    pub fn new(color: Color, roughness: f32) -> Self {
+        Self { color, roughness, film: None }
+    }
+
+    pub fn set_thin_film(mut self, film: ThinFilm) -> Self {
+        self.film = Some(film);
+        self
+    }
+
+    /// Fraction of the light reflected off a microfacet at `cos_theta` to it
+    pub fn get_fresnel(&self, cos_theta: f32) -> Color {
+        match &self.film {
+            Some(film) => film.get_conductor_reflectance(cos_theta, &self.color),
+            None => schlick_fresnel(&self.color, cos_theta)
+        }
+    }
+
+    /// Scales single scattering up by the light that would leave after more bounces (Turquin 2019)
+    fn get_compensation(&self, ggx: &Ggx, cos_o: f32) -> Color {
+        let albedo = ggx.get_albedo(cos_o).max(1e-3);
+        let factor = (1.0 - albedo) / albedo;
+
+        Color::rgb(
+            1.0 + self.color.r * factor,
+            1.0 + self.color.g * factor,
+            1.0 + self.color.b * factor
+        )
+    }
+}
+
+impl Bsdf for RoughConductor {
+    fn sample(&self, wo: &Vec3, normal: &Vec3) -> Option<BsdfSample> {
+        let frame = Frame::new(&face_forward(normal, wo));
+        let ggx = Ggx::new(self.roughness);
+
+        let local_wo = frame.to_local(wo);
+        if local_wo.z <= 0.0 { return None; }
+
+        let h = ggx.sample_visible_normal(&local_wo, random(), random());
+        let local_wi = h.mul_by(2.0 * local_wo.dot(&h)).sub(&local_wo);
+        if local_wi.z <= 0.0 { return None; }
+
+        let fresnel = self.get_fresnel(local_wo.dot(&h));
+        let visibility = ggx.get_shadowing(&local_wo, &local_wi) / ggx.get_masking(&local_wo);
+
+        Some(BsdfSample {
+            direction: frame.to_world(&local_wi),
+            weight: fresnel.mul(&self.get_compensation(&ggx, local_wo.z)).mul_by(visibility),
+            pdf: ggx.get_reflection_pdf(&local_wo, &h),
+            is_specular: false,
+            is_hero_only: false
+        })
+    }
+
+    fn eval(&self, wo: &Vec3, wi: &Vec3, normal: &Vec3) -> Color {
+        let frame = Frame::new(&face_forward(normal, wo));
+        let ggx = Ggx::new(self.roughness);
+
+        let (local_wo, local_wi) = (frame.to_local(wo), frame.to_local(wi));
+        if local_wo.z <= 0.0 || local_wi.z <= 0.0 { return Color::rgb(0.0, 0.0, 0.0); }
+
+        let h = local_wo.add(&local_wi).normalize();
+        let fresnel = self.get_fresnel(local_wo.dot(&h));
+        let specular = ggx.get_distribution(&h) * ggx.get_shadowing(&local_wo, &local_wi)
+            / (4.0 * local_wo.z * local_wi.z);
+
+        fresnel.mul(&self.get_compensation(&ggx, local_wo.z)).mul_by(specular)
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3, normal: &Vec3) -> f32 {
+        let frame = Frame::new(&face_forward(normal, wo));
+        let ggx = Ggx::new(self.roughness);
+
+        let (local_wo, local_wi) = (frame.to_local(wo), frame.to_local(wi));
+        if local_wi.z <= 0.0 { return 0.0; }
+
+        ggx.get_reflection_pdf(&local_wo, &local_wo.add(&local_wi).normalize())
+    }
+}
\ No newline at end of file
@@ -0,0 +1,180 @@
+use super::{Bsdf, BsdfSample, ThinFilm, reflect};
+use crate::util::{Color, random, vec::*};
+
+/// Which formula a [Dielectric] uses for how much light it reflects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fresnel {
+    /// Full Fresnel equations for unpolarized light
+    Exact,
+    /// Schlick's cheaper approximation
+    Schlick
+}
+
+/// Wavelength dependent index of refraction, which splits white light into its colors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dispersion {
+    /// `n = a + b / λ²`, with `λ` in micrometres
+    Cauchy { a: f32, b: f32 },
+    /// `n² = 1 + Σ bᵢ λ² / (λ² - cᵢ)`, with `λ` in micrometres and `cᵢ` in square micrometres
+    Sellmeier { b: [f32; 3], c: [f32; 3] }
+}
+
+impl Dispersion {
+    /// Wavelength of the sodium D line, in nanometres, where indices of refraction are usually quoted
+    pub const SODIUM_D: f32 = 589.3;
+
+    /// Common borosilicate crown glass
+    pub const BK7: Self = Self::Sellmeier {
+        b: [1.039_612, 0.231_792_34, 1.010_469_4],
+        c: [0.006_000_699, 0.020_017_914, 103.560_65]
+    };
+
+    pub const DIAMOND: Self = Self::Sellmeier {
+        b: [0.3306, 4.3356, 0.0],
+        c: [0.030_625, 0.011_236, 0.0]
+    };
+
+    /// Index of refraction at `wavelength` nanometres
+    pub fn get_ior(&self, wavelength: f32) -> f32 {
+        let micrometres = wavelength / 1000.0;
+        let squared = micrometres * micrometres;
+
+        match self {
+            Self::Cauchy { a, b } => a + b / squared,
+            Self::Sellmeier { b, c } => (1.0 + (0..3).map(|i| b[i] * squared / (squared - c[i])).sum::<f32>()).sqrt()
+        }
+    }
+}
+
+/// Smooth transparent surface such as glass or water, reflecting and refracting light
+#[derive(Debug, Clone, Copy)]
+pub struct Dielectric {
+    /// Index of refraction of the inside relative to the outside
+    pub ior: f32,
+    /// Color of the refracted light
+    pub tint: Color,
+    pub fresnel: Fresnel,
+    /// Only the first channel of the light makes it through, see [Dielectric::set_hero_only]
+    pub is_hero_only: bool,
+    /// Coating on the outside whose interference colors the reflections, see [ThinFilm]
+    pub film: Option<ThinFilm>
+}
+
+impl Dielectric {
+    pub fn new(ior: f32, tint: Color) -> Self {
+        Self { ior, tint, fresnel: Fresnel::Exact, is_hero_only: false, film: None }
+    }
+
+    /// In spectral rendering the index of refraction of dispersive surfaces only holds for the hero wavelength
+    /// in the first channel, so the light at the other wavelengths is dropped.
+    /// Paths scale the hero up to make up for it, once, see [BsdfSample::is_hero_only]
+    pub fn set_hero_only(mut self, is_hero_only: bool) -> Self {
+        self.is_hero_only = is_hero_only;
+        self
+    }
+
+    fn get_weight(&self, weight: Color) -> Color {
+        if !self.is_hero_only { return weight; }
+        Color::rgb(weight.r, 0.0, 0.0)
+    }
+
+    pub fn set_fresnel(mut self, fresnel: Fresnel) -> Self {
+        self.fresnel = fresnel;
+        self
+    }
+
+    pub fn set_thin_film(mut self, film: ThinFilm) -> Self {
+        self.film = Some(film);
+        self
+    }
+
+    fn get_reflectance(&self, cos_i: f32, eta: f32) -> Color {
+        if let Some(film) = &self.film {
+            return film.get_reflectance(cos_i, self.ior, eta < 1.0);
+        }
+
+        let reflectance = match self.fresnel {
+            Fresnel::Exact => fresnel_dielectric(cos_i, eta),
+            Fresnel::Schlick => schlick_dielectric(cos_i, eta)
+        };
+        Color::rgb(reflectance, reflectance, reflectance)
+    }
+}
+
+impl Bsdf for Dielectric {
+    fn sample(&self, wo: &Vec3, normal: &Vec3) -> Option<BsdfSample> {
+        // Flip everything around when leaving the inside
+        let cos_o = wo.dot(normal);
+        let (eta, normal) = if cos_o > 0.0 { (self.ior, *normal) } else { (1.0 / self.ior, normal.invert()) };
+        let cos_i = cos_o.abs();
+
+        // Films reflect each channel differently, so reflecting is picked by their average
+        let reflectance = self.get_reflectance(cos_i, eta);
+        let probability = (reflectance.r + reflectance.g + reflectance.b) / 3.0;
+
+        if random() < probability {
+            return Some(BsdfSample {
+                direction: reflect(wo, &normal),
+                weight: self.get_weight(reflectance.div_by(probability)),
+                pdf: probability,
+                is_specular: true,
+                is_hero_only: self.is_hero_only
+            });
+        }
+
+        // Only reached without total internal reflection, where the reflectance is 1.0
+        let cos_t = (1.0 - (1.0 - cos_i * cos_i) / (eta * eta)).max(0.0).sqrt();
+        let direction = wo.invert().div_by(eta).add(&normal.mul_by(cos_i / eta - cos_t));
+
+        Some(BsdfSample {
+            direction: direction.normalize(),
+            weight: self.get_weight(Color::rgb(1.0 - reflectance.r, 1.0 - reflectance.g, 1.0 - reflectance.b)
+                .div_by(1.0 - probability)
+                .mul(&self.tint)),
+            pdf: 1.0 - probability,
+            is_specular: true,
+            is_hero_only: self.is_hero_only
+        })
+    }
+
+    fn eval(&self, _wo: &Vec3, _wi: &Vec3, _normal: &Vec3) -> Color {
+        Color::rgb(0.0, 0.0, 0.0)
+    }
+
+    fn pdf(&self, _wo: &Vec3, _wi: &Vec3, _normal: &Vec3) -> f32 {
+        0.0
+    }
+}
+
+/// Reflectance of unpolarized light hitting a dielectric boundary.
+/// `eta` is the index of refraction of the far side relative to the near side
+pub fn fresnel_dielectric(cos_i: f32, eta: f32) -> f32 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+
+    // Total internal reflection
+    if sin2_t >= 1.0 { return 1.0; }
+
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+/// Schlick's approximation of [fresnel_dielectric]
+pub fn schlick_dielectric(cos_i: f32, eta: f32) -> f32 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let r0 = ((eta - 1.0) / (eta + 1.0)).powi(2);
+
+    // Leaving the denser side, the angle that matters is the one of the refracted ray
+    let cos = if eta < 1.0 {
+        let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+        if sin2_t >= 1.0 { return 1.0; }
+        (1.0 - sin2_t).sqrt()
+    } else {
+        cos_i
+    };
+
+    r0 + (1.0 - r0) * (1.0 - cos).powi(5)
+}
\ No newline at end of file
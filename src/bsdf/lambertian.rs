@@ -0,0 +1,44 @@
+use std::f32::consts::FRAC_1_PI;
+
+use super::{Bsdf, BsdfSample, Frame, face_forward, sample_cosine_hemisphere};
+use crate::util::{Color, vec::*};
+
+/// Perfectly diffuse surface, scattering light equally in every direction
+#[derive(Debug, Clone, Copy)]
+pub struct Lambertian {
+    pub albedo: Color
+}
+
+impl Lambertian {
+    pub fn new(albedo: Color) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Bsdf for Lambertian {
+    fn sample(&self, wo: &Vec3, normal: &Vec3) -> Option<BsdfSample> {
+        let normal = face_forward(normal, wo);
+        let local = sample_cosine_hemisphere();
+        if local.z <= 0.0 { return None; }
+
+        Some(BsdfSample {
+            direction: Frame::new(&normal).to_world(&local),
+            weight: self.albedo,
+            pdf: local.z * FRAC_1_PI,
+            is_specular: false,
+            is_hero_only: false
+        })
+    }
+
+    fn eval(&self, wo: &Vec3, wi: &Vec3, normal: &Vec3) -> Color {
+        let normal = face_forward(normal, wo);
+        if wi.dot(&normal) <= 0.0 { return Color::rgb(0.0, 0.0, 0.0); }
+
+        self.albedo.mul_by(FRAC_1_PI)
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3, normal: &Vec3) -> f32 {
+        let normal = face_forward(normal, wo);
+        wi.dot(&normal).max(0.0) * FRAC_1_PI
+    }
+}
\ No newline at end of file
@@ -0,0 +1,140 @@
+use std::{f32::consts::PI, sync::OnceLock};
+
+use crate::util::vec::*;
+
+/// Resolution of the directional albedo table along both the cosine and roughness axes
+const ALBEDO_RESOLUTION: usize = 32;
+/// Stratified samples per axis used to integrate each entry of the table
+const ALBEDO_SAMPLES: usize = 16;
+
+/// GGX / Trowbridge-Reitz distribution of microfacet normals with Smith shadowing.
+/// Works in shading space where the macro surface normal is `+z`
+#[derive(Debug, Clone, Copy)]
+pub struct Ggx {
+    pub alpha: f32
+}
+
+impl Ggx {
+    /// `roughness` is perceptual, squared into the width of the distribution
+    pub fn new(roughness: f32) -> Self {
+        Self { alpha: roughness.clamp(0.0, 1.0).powi(2).max(1e-3) }
+    }
+
+    /// Density of microfacets facing `h`
+    pub fn get_distribution(&self, h: &Vec3) -> f32 {
+        if h.z <= 0.0 { return 0.0; }
+
+        let alpha2 = self.alpha * self.alpha;
+        let denominator = h.z * h.z * (alpha2 - 1.0) + 1.0;
+        alpha2 / (PI * denominator * denominator)
+    }
+
+    /// Smith's auxiliary function Λ
+    fn get_lambda(&self, w: &Vec3) -> f32 {
+        let cos2 = w.z * w.z;
+        if cos2 <= 0.0 { return f32::INFINITY; }
+
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        0.5 * ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0)
+    }
+
+    /// Fraction of microfacets visible from `w`
+    pub fn get_masking(&self, w: &Vec3) -> f32 {
+        1.0 / (1.0 + self.get_lambda(w))
+    }
+
+    /// Height correlated fraction of microfacets visible from both `wo` and `wi`
+    pub fn get_shadowing(&self, wo: &Vec3, wi: &Vec3) -> f32 {
+        1.0 / (1.0 + self.get_lambda(wo) + self.get_lambda(wi))
+    }
+
+    /// Density of [Ggx::sample_visible_normal] picking `wi` after reflecting `wo` about the normal
+    pub fn get_reflection_pdf(&self, wo: &Vec3, h: &Vec3) -> f32 {
+        if wo.z <= 0.0 { return 0.0; }
+        self.get_masking(wo) * self.get_distribution(h) / (4.0 * wo.z)
+    }
+
+    /// Picks a microfacet normal visible from `wo` (Heitz 2018)
+    pub fn sample_visible_normal(&self, wo: &Vec3, u1: f32, u2: f32) -> Vec3 {
+        // Stretch the view direction into the space of a hemisphere
+        let view = Vec3::new(self.alpha * wo.x, self.alpha * wo.y, wo.z).normalize();
+
+        let length2 = view.x * view.x + view.y * view.y;
+        let t1 = if length2 > 0.0 {
+            Vec3::new(-view.y, view.x, 0.0).div_by(length2.sqrt())
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = view.cross(&t1);
+
+        // Uniform point on the disk, squashed onto the visible half
+        let radius = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = radius * phi.cos();
+        let s = 0.5 * (1.0 + view.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * radius * phi.sin();
+        let p3 = (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+
+        let normal = t1.mul_by(p1).add(&t2.mul_by(p2)).add(&view.mul_by(p3));
+
+        // Unstretch back onto the distribution
+        Vec3::new(self.alpha * normal.x, self.alpha * normal.y, normal.z.max(0.0)).normalize()
+    }
+
+    /// Fraction of light a perfectly reflective surface with this distribution reflects
+    /// when seen at `cos_theta` in a single bounce. Light lost to bounces between microfacets
+    /// is what makes rough surfaces look darker than they should
+    pub fn get_albedo(&self, cos_theta: f32) -> f32 {
+        let table = ALBEDO_TABLE.get_or_init(build_albedo_table);
+        let last = (ALBEDO_RESOLUTION - 1) as f32;
+
+        let x = cos_theta.clamp(0.0, 1.0) * last;
+        let y = self.alpha.sqrt().clamp(0.0, 1.0) * last;
+        let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(ALBEDO_RESOLUTION - 1), (y0 + 1).min(ALBEDO_RESOLUTION - 1));
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+
+        let at = |x: usize, y: usize| table[y * ALBEDO_RESOLUTION + x];
+        let top = at(x0, y0) * (1.0 - fx) + at(x1, y0) * fx;
+        let bottom = at(x0, y1) * (1.0 - fx) + at(x1, y1) * fx;
+
+        top * (1.0 - fy) + bottom * fy
+    }
+}
+
+static ALBEDO_TABLE: OnceLock<Vec<f32>> = OnceLock::new();
+
+/// Integrates the single scattering albedo over cosines and roughnesses,
+/// with stratified samples so the table is the same on every run
+fn build_albedo_table() -> Vec<f32> {
+    let mut table = Vec::with_capacity(ALBEDO_RESOLUTION * ALBEDO_RESOLUTION);
+    let last = (ALBEDO_RESOLUTION - 1) as f32;
+
+    for y in 0..ALBEDO_RESOLUTION {
+        let roughness = y as f32 / last;
+        let ggx = Ggx::new(roughness);
+
+        for x in 0..ALBEDO_RESOLUTION {
+            let cos_theta = (x as f32 / last).max(1e-3);
+            let wo = Vec3::new((1.0 - cos_theta * cos_theta).sqrt(), 0.0, cos_theta);
+            let mut total = 0.0;
+
+            for i in 0..ALBEDO_SAMPLES {
+                for j in 0..ALBEDO_SAMPLES {
+                    let u1 = (i as f32 + 0.5) / ALBEDO_SAMPLES as f32;
+                    let u2 = (j as f32 + 0.5) / ALBEDO_SAMPLES as f32;
+
+                    let h = ggx.sample_visible_normal(&wo, u1, u2);
+                    let wi = h.mul_by(2.0 * wo.dot(&h)).sub(&wo);
+                    if wi.z <= 0.0 { continue; }
+
+                    total += ggx.get_shadowing(&wo, &wi) / ggx.get_masking(&wo);
+                }
+            }
+
+            table.push(total / (ALBEDO_SAMPLES * ALBEDO_SAMPLES) as f32);
+        }
+    }
+
+    table
+}
\ No newline at end of file
@@ -0,0 +1,49 @@
+use super::{Bsdf, BsdfSample};
+use crate::util::{Color, random, vec::*};
+
+/// Blend of two BSDFs, `weight` being how much of `second` is used
+pub struct Mix {
+    pub first: Box<dyn Bsdf>,
+    pub second: Box<dyn Bsdf>,
+    pub weight: f32
+}
+
+impl Mix {
+    pub fn new(first: Box<dyn Bsdf>, second: Box<dyn Bsdf>, weight: f32) -> Self {
+        Self { first, second, weight: weight.clamp(0.0, 1.0) }
+    }
+}
+
+impl Bsdf for Mix {
+    fn sample(&self, wo: &Vec3, normal: &Vec3) -> Option<BsdfSample> {
+        let sample = if random() < self.weight {
+            self.second.sample(wo, normal)?
+        } else {
+            self.first.sample(wo, normal)?
+        };
+
+        // Delta lobes can't be reached by the other BSDF, picking them already accounts for the weight
+        if sample.is_specular { return Some(sample); }
+
+        let pdf = self.pdf(wo, &sample.direction, normal);
+        if pdf <= 0.0 { return None; }
+
+        let cos_i = sample.direction.dot(normal).abs();
+
+        Some(BsdfSample {
+            weight: self.eval(wo, &sample.direction, normal).mul_by(cos_i / pdf),
+            pdf,
+            ..sample
+        })
+    }
+
+    fn eval(&self, wo: &Vec3, wi: &Vec3, normal: &Vec3) -> Color {
+        self.first.eval(wo, wi, normal).mul_by(1.0 - self.weight)
+            .add(&self.second.eval(wo, wi, normal).mul_by(self.weight))
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3, normal: &Vec3) -> f32 {
+        self.first.pdf(wo, wi, normal) * (1.0 - self.weight)
+            + self.second.pdf(wo, wi, normal) * self.weight
+    }
+}
\ No newline at end of file
@@ -0,0 +1,305 @@
+mod lambertian;
+mod specular;
+mod conductor;
+mod mix;
+mod dielectric;
+mod microfacet;
+mod principled;
+mod thin_film;
+
+pub use lambertian::Lambertian;
+pub use specular::Specular;
+pub use conductor::RoughConductor;
+pub use mix::Mix;
+pub use microfacet::Ggx;
+pub use principled::Principled;
+pub use dielectric::{Dielectric, Dispersion, Fresnel, fresnel_dielectric, schlick_dielectric};
+pub use thin_film::{ThinFilm, RGB_WAVELENGTHS};
+
+use std::f32::consts::PI;
+
+use crate::util::{Color, random, vec::*};
+
+/// Direction picked by [Bsdf::sample]
+#[derive(Debug, Clone, Copy)]
+pub struct BsdfSample {
+    /// Direction light arrives from, pointing away from the surface
+    pub direction: Vec3,
+    /// `eval * cos / pdf`, what the throughput of a path is multiplied by
+    pub weight: Color,
+    pub pdf: f32,
+    /// Picked from a delta distribution, which [Bsdf::eval] and [Bsdf::pdf] never return
+    pub is_specular: bool,
+    /// Only the hero wavelength in the first channel follows the direction, see [Dielectric::set_hero_only]
+    pub is_hero_only: bool
+}
+
+/// Describes how a surface scatters light.
+/// All directions are in world space and point away from the surface, `wo` towards the viewer
+/// and `wi` towards the light. `normal` is the outward facing normal of the surface
+pub trait Bsdf {
+    /// Picks the direction light arrives from, `None` when the surface absorbs it
+    fn sample(&self, wo: &Vec3, normal: &Vec3) -> Option<BsdfSample>;
+    /// How much of the light arriving from `wi` is scattered towards `wo`
+    fn eval(&self, wo: &Vec3, wi: &Vec3, normal: &Vec3) -> Color;
+    /// Probability density of [Bsdf::sample] picking `wi`
+    fn pdf(&self, wo: &Vec3, wi: &Vec3, normal: &Vec3) -> f32;
+}
+
+/// Orthonormal basis around a normal, used to move directions in and out of shading space
+#[derive(Debug, Clone, Copy)]
+pub struct Frame {
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
+    pub normal: Vec3
+}
+
+impl Frame {
+    /// Builds a frame around a unit `normal` (Duff et al. 2017)
+    pub fn new(normal: &Vec3) -> Self {
+        let sign = 1.0f32.copysign(normal.z);
+        let a = -1.0 / (sign + normal.z);
+        let b = normal.x * normal.y * a;
+
+        Self {
+            tangent: Vec3::new(1.0 + sign * normal.x * normal.x * a, sign * b, -sign * normal.x),
+            bitangent: Vec3::new(b, sign + normal.y * normal.y * a, -normal.y),
+            normal: *normal
+        }
+    }
+
+    /// Expresses a world space direction in the frame, `z` being along the normal
+    pub fn to_local(&self, direction: &Vec3) -> Vec3 {
+        Vec3::new(
+            direction.dot(&self.tangent),
+            direction.dot(&self.bitangent),
+            direction.dot(&self.normal)
+        )
+    }
+
+    pub fn to_world(&self, direction: &Vec3) -> Vec3 {
+        self.tangent.mul_by(direction.x)
+            .add(&self.bitangent.mul_by(direction.y))
+            .add(&self.normal.mul_by(direction.z))
+    }
+}
+
+/// Flips `normal` onto the same side of the surface as `direction`
+pub fn face_forward(normal: &Vec3, direction: &Vec3) -> Vec3 {
+    if normal.dot(direction) < 0.0 { normal.invert() } else { *normal }
+}
+
+/// Mirrors `wo` around `normal`, both pointing away from the surface
+pub fn reflect(wo: &Vec3, normal: &Vec3) -> Vec3 {
+    normal.mul_by(2.0 * wo.dot(normal)).sub(wo)
+}
+
+/// Schlick's approximation of the reflectance of a conductor tinted by `f0`
+pub fn schlick_fresnel(f0: &Color, cos_theta: f32) -> Color {
+    let factor = (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5);
+
+    Color::rgb(
+        f0.r + (1.0 - f0.r) * factor,
+        f0.g + (1.0 - f0.g) * factor,
+        f0.b + (1.0 - f0.b) * factor
+    )
+}
+
+/// Perceived brightness of a linear color
+pub fn luminance(color: &Color) -> f32 {
+    0.2126 * color.r + 0.7152 * color.g + 0.0722 * color.b
+}
+
+/// Cosine weighted direction on the hemisphere around `+z`
+fn sample_cosine_hemisphere() -> Vec3 {
+    let radius = random().sqrt();
+    let phi = 2.0 * PI * random();
+
+    Vec3::new(
+        radius * phi.cos(),
+        radius * phi.sin(),
+        (1.0 - radius * radius).max(0.0).sqrt()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Estimates how much light a surface lit from every direction reflects towards `wo`
+    fn albedo(bsdf: &dyn Bsdf, wo: &Vec3, normal: &Vec3) -> Color {
+        let samples = 20_000;
+        let mut total = Color::rgb(0.0, 0.0, 0.0);
+
+        for _ in 0..samples {
+            if let Some(sample) = bsdf.sample(wo, normal) {
+                total.add_mut(&sample.weight);
+            }
+        }
+
+        total.div_by(samples as f32)
+    }
+
+    #[test]
+    fn frame_is_orthonormal() {
+        for normal in [Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0), Vec3::new(1.0, 2.0, -3.0).normalize()] {
+            let frame = Frame::new(&normal);
+            let direction = Vec3::new(0.3, -0.5, 0.8);
+            let roundtrip = frame.to_world(&frame.to_local(&direction));
+
+            assert!(frame.tangent.dot(&frame.normal).abs() < 1e-5);
+            assert!(frame.bitangent.dot(&frame.normal).abs() < 1e-5);
+            assert!(frame.tangent.dot(&frame.bitangent).abs() < 1e-5);
+            assert!(roundtrip.sub(&direction).magnitude() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn lambertian_conserves_energy() {
+        let bsdf = Lambertian::new(Color::rgb(0.8, 0.8, 0.8));
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let wo = Vec3::new(0.0, 1.0, 1.0).normalize();
+
+        assert!((albedo(&bsdf, &wo, &normal).r - 0.8).abs() < 1e-3);
+
+        // Sampling and evaluating agree
+        let sample = bsdf.sample(&wo, &normal).unwrap();
+        let eval = bsdf.eval(&wo, &sample.direction, &normal);
+        let weight = eval.r * sample.direction.dot(&normal) / bsdf.pdf(&wo, &sample.direction, &normal);
+        assert!((weight - sample.weight.r).abs() < 1e-3);
+    }
+
+    #[test]
+    fn specular_mirrors() {
+        let bsdf = Specular::new(Color::rgb(1.0, 1.0, 1.0));
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let sample = bsdf.sample(&Vec3::new(1.0, 1.0, 0.0).normalize(), &normal).unwrap();
+
+        assert!(sample.is_specular);
+        assert!(sample.direction.sub(&Vec3::new(-1.0, 1.0, 0.0).normalize()).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn dielectric_fresnel() {
+        // Glass reflects 4% head on, both formulas agree there
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-4);
+        assert!((schlick_dielectric(1.0, 1.5) - 0.04).abs() < 1e-4);
+
+        // Past the critical angle everything is reflected
+        let grazing = 0.2;
+        assert_eq!(fresnel_dielectric(grazing, 1.0 / 1.5), 1.0);
+        assert_eq!(schlick_dielectric(grazing, 1.0 / 1.5), 1.0);
+        assert!(fresnel_dielectric(grazing, 1.5) < 1.0);
+    }
+
+    #[test]
+    fn dielectric_refracts() {
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let wo = Vec3::new(1.0, 1.0, 0.0).normalize();
+
+        for bsdf in [Dielectric::new(1.5, Color::rgb(1.0, 1.0, 1.0)), Dielectric::new(1.5, Color::rgb(1.0, 1.0, 1.0)).set_fresnel(Fresnel::Schlick)] {
+            for _ in 0..64 {
+                let sample = bsdf.sample(&wo, &normal).unwrap();
+                if sample.direction.y > 0.0 { continue; }
+
+                // Snell's law: sin of 45° over 1.5
+                let sin_t = (1.0 - sample.direction.y * sample.direction.y).sqrt();
+                assert!((sin_t - std::f32::consts::FRAC_1_SQRT_2 / 1.5).abs() < 1e-4);
+                assert!(sample.direction.x < 0.0);
+            }
+        }
+
+        // Leaving the glass at a grazing angle always reflects back inside
+        let bsdf = Dielectric::new(1.5, Color::rgb(1.0, 1.0, 1.0));
+        let wo = Vec3::new(1.0, -0.2, 0.0).normalize();
+        for _ in 0..64 {
+            assert!(bsdf.sample(&wo, &normal).unwrap().direction.y < 0.0);
+        }
+    }
+
+    #[test]
+    fn dielectric_disperses() {
+        // Quoted indices at the sodium D line, with blue bending more than red
+        assert!((Dispersion::BK7.get_ior(Dispersion::SODIUM_D) - 1.5168).abs() < 1e-3);
+        assert!((Dispersion::DIAMOND.get_ior(Dispersion::SODIUM_D) - 2.417).abs() < 1e-3);
+        for dispersion in [Dispersion::BK7, Dispersion::DIAMOND, Dispersion::Cauchy { a: 1.5, b: 0.004 }] {
+            assert!(dispersion.get_ior(450.0) > dispersion.get_ior(650.0));
+        }
+
+        // Only the hero wavelength in the first channel gets through dispersive surfaces
+        let bsdf = Dielectric::new(1.5, Color::rgb(0.5, 0.5, 0.5)).set_hero_only(true);
+        let sample = bsdf.sample(&Vec3::new(0.0, 1.0, 0.0), &Vec3::new(0.0, 1.0, 0.0)).unwrap();
+        assert!(sample.is_hero_only && sample.weight.r > 0.0 && sample.weight.g == 0.0 && sample.weight.b == 0.0);
+    }
+
+    #[test]
+    fn thin_films_keep_energy() {
+        // Whatever the film doesn't reflect is refracted, in every channel
+        let bsdf = Dielectric::new(1.5, Color::rgb(1.0, 1.0, 1.0)).set_thin_film(ThinFilm::new(250.0, 1.33));
+        let wo = Vec3::new(1.0, 1.0, 0.0).normalize();
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+
+        let count = 20000;
+        let mut total = Color::rgb(0.0, 0.0, 0.0);
+        for _ in 0..count {
+            total.add_mut(&bsdf.sample(&wo, &normal).unwrap().weight);
+        }
+        total = total.div_by(count as f32);
+
+        for value in [total.r, total.g, total.b] {
+            assert!((value - 1.0).abs() < 0.03, "{total:?}");
+        }
+    }
+
+    #[test]
+    fn rough_conductor_conserves_energy() {
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        // Rough and head on, half the samples are absorbed and the rest weigh up to ~3,
+        // for a standard deviation of ~1.2 per sample. 40 000 samples leave a standard error
+        // of ~0.006, so the 0.03 tolerance is five of them
+        let samples = 40_000;
+
+        for roughness in [0.1, 0.5, 1.0] {
+            for wo in [Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 1.5).normalize()] {
+                // A white furnace: a perfectly reflective metal gives back everything, even when rough
+                let bsdf = RoughConductor::new(Color::rgb(1.0, 1.0, 1.0), roughness);
+                let mut total = 0.0;
+
+                for _ in 0..samples {
+                    // Directions reflected below the surface are absorbed and count as zero
+                    let Some(sample) = bsdf.sample(&wo, &normal) else { continue };
+                    total += sample.weight.r;
+
+                    let eval = bsdf.eval(&wo, &sample.direction, &normal);
+                    let pdf = bsdf.pdf(&wo, &sample.direction, &normal);
+                    let weight = eval.r * sample.direction.dot(&normal) / pdf;
+                    assert!((weight - sample.weight.r).abs() < 1e-3);
+                    // Narrow lobes make the half vector rebuilt from the directions sensitive to rounding
+                    assert!((pdf - sample.pdf).abs() < 1e-2 * pdf);
+                }
+
+                let reflected = total / samples as f32;
+                assert!((reflected - 1.0).abs() < 0.03, "roughness {roughness} reflected {reflected}");
+            }
+        }
+    }
+
+    #[test]
+    fn principled_blends_metallic() {
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let wo = Vec3::new(0.0, 1.0, 0.5).normalize();
+        let red = Color::rgb(1.0, 0.0, 0.0);
+
+        // Plastic keeps its color in the diffuse base while the highlight stays white,
+        // metal tints the highlight
+        let plastic = Principled::new(red, 0.3, 0.0);
+        let metal = Principled::new(red, 0.3, 1.0);
+        assert!((plastic.specular.color.g - 0.04).abs() < 1e-6);
+        assert_eq!(metal.diffuse.albedo.r, 0.0);
+        assert_eq!(metal.specular.color.g, 0.0);
+
+        for bsdf in [plastic, metal, Principled::new(Color::rgb(1.0, 1.0, 1.0), 1.0, 0.5)] {
+            assert!(albedo(&bsdf, &wo, &normal).r <= 1.02);
+        }
+    }
+}
\ No newline at end of file
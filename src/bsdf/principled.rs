@@ -0,0 +1,80 @@
+use super::{Bsdf, BsdfSample, Lambertian, RoughConductor, ThinFilm, face_forward, luminance};
+use crate::util::{Color, random, vec::*};
+
+/// Reflectance of non-metals at normal incidence
+const DIELECTRIC_REFLECTANCE: f32 = 0.04;
+
+/// glTF style metallic-roughness surface: a diffuse base under a GGX specular layer.
+/// `metallic` moves the specular reflectance from that of plastics to the albedo
+/// while the diffuse base fades out
+#[derive(Debug, Clone, Copy)]
+pub struct Principled {
+    pub diffuse: Lambertian,
+    pub specular: RoughConductor
+}
+
+impl Principled {
+    pub fn new(albedo: Color, roughness: f32, metallic: f32) -> Self {
+        let metallic = metallic.clamp(0.0, 1.0);
+        let reflectance = Color::rgb(DIELECTRIC_REFLECTANCE, DIELECTRIC_REFLECTANCE, DIELECTRIC_REFLECTANCE);
+
+        Self {
+            diffuse: Lambertian::new(albedo.mul_by(1.0 - metallic)),
+            specular: RoughConductor::new(reflectance.mul_by(1.0 - metallic).add(&albedo.mul_by(metallic)), roughness)
+        }
+    }
+
+    /// Coats the specular layer, see [ThinFilm]
+    pub fn set_thin_film(mut self, film: ThinFilm) -> Self {
+        self.specular = self.specular.set_thin_film(film);
+        self
+    }
+
+    /// Light the specular layer reflects never reaches the diffuse base
+    fn get_diffuse_weight(&self, wo: &Vec3, normal: &Vec3) -> f32 {
+        let fresnel = self.specular.get_fresnel(wo.dot(normal).abs());
+        1.0 - fresnel.r.max(fresnel.g).max(fresnel.b)
+    }
+
+    /// Chance of sampling the specular layer, proportional to how much it reflects
+    fn get_specular_probability(&self, wo: &Vec3, normal: &Vec3) -> f32 {
+        let specular = luminance(&self.specular.get_fresnel(wo.dot(normal).abs()));
+        let diffuse = luminance(&self.diffuse.albedo) * self.get_diffuse_weight(wo, normal);
+        if specular + diffuse <= 0.0 { return 1.0; }
+
+        specular / (specular + diffuse)
+    }
+}
+
+impl Bsdf for Principled {
+    fn sample(&self, wo: &Vec3, normal: &Vec3) -> Option<BsdfSample> {
+        let sample = if random() < self.get_specular_probability(wo, normal) {
+            self.specular.sample(wo, normal)?
+        } else {
+            self.diffuse.sample(wo, normal)?
+        };
+
+        let pdf = self.pdf(wo, &sample.direction, normal);
+        if pdf <= 0.0 { return None; }
+
+        let cos_i = sample.direction.dot(&face_forward(normal, wo));
+
+        Some(BsdfSample {
+            weight: self.eval(wo, &sample.direction, normal).mul_by(cos_i / pdf),
+            pdf,
+            ..sample
+        })
+    }
+
+    fn eval(&self, wo: &Vec3, wi: &Vec3, normal: &Vec3) -> Color {
+        self.diffuse.eval(wo, wi, normal).mul_by(self.get_diffuse_weight(wo, normal))
+            .add(&self.specular.eval(wo, wi, normal))
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3, normal: &Vec3) -> f32 {
+        let probability = self.get_specular_probability(wo, normal);
+
+        self.diffuse.pdf(wo, wi, normal) * (1.0 - probability)
+            + self.specular.pdf(wo, wi, normal) * probability
+    }
+}
\ No newline at end of file
@@ -0,0 +1,46 @@
+use super::{Bsdf, BsdfSample, ThinFilm, face_forward, reflect, schlick_fresnel};
+use crate::util::{Color, vec::*};
+
+/// Perfectly smooth mirror, tinted by `color` at normal incidence
+#[derive(Debug, Clone, Copy)]
+pub struct Specular {
+    pub color: Color,
+    /// Coating whose interference colors the reflections, see [ThinFilm]
+    pub film: Option<ThinFilm>
+}
+
+impl Specular {
+    pub fn new(color: Color) -> Self {
+        Self { color, film: None }
+    }
+
+    pub fn set_thin_film(mut self, film: ThinFilm) -> Self {
+        self.film = Some(film);
+        self
+    }
+}
+
+impl Bsdf for Specular {
+    fn sample(&self, wo: &Vec3, normal: &Vec3) -> Option<BsdfSample> {
+        let normal = face_forward(normal, wo);
+
+        Some(BsdfSample {
+            direction: reflect(wo, &normal),
+            weight: match &self.film {
+                Some(film) => film.get_conductor_reflectance(wo.dot(&normal), &self.color),
+                None => schlick_fresnel(&self.color, wo.dot(&normal))
+            },
+            pdf: 1.0,
+            is_specular: true,
+            is_hero_only: false
+        })
+    }
+
+    fn eval(&self, _wo: &Vec3, _wi: &Vec3, _normal: &Vec3) -> Color {
+        Color::rgb(0.0, 0.0, 0.0)
+    }
+
+    fn pdf(&self, _wo: &Vec3, _wi: &Vec3, _normal: &Vec3) -> f32 {
+        0.0
+    }
+}
\ No newline at end of file
@@ -0,0 +1,128 @@
+use std::f32::consts::PI;
+
+use crate::util::Color;
+
+/// Wavelengths, in nanometres, the red, green and blue channels stand for outside of spectral rendering
+pub const RGB_WAVELENGTHS: [f32; 3] = [630.0, 532.0, 465.0];
+
+/// Thin transparent coating, like soap or oil, whose reflections off its top and bottom interfere.
+/// Changes the Fresnel reflectance of the surface under it per wavelength, giving iridescent colors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThinFilm {
+    /// In nanometres
+    pub thickness: f32,
+    pub ior: f32,
+    /// Wavelength each color channel stands for, in nanometres
+    pub wavelengths: [f32; 3]
+}
+
+impl ThinFilm {
+    pub fn new(thickness: f32, ior: f32) -> Self {
+        Self { thickness, ior, wavelengths: RGB_WAVELENGTHS }
+    }
+
+    pub fn set_wavelengths(mut self, wavelengths: [f32; 3]) -> Self {
+        self.wavelengths = wavelengths;
+        self
+    }
+
+    /// Reflectance of the film over a dielectric with index of refraction `base_ior`.
+    /// `cos_i` is measured on the side the light comes from, which is inside the base when `is_inside`
+    pub fn get_reflectance(&self, cos_i: f32, base_ior: f32, is_inside: bool) -> Color {
+        let [r, g, b] = self.wavelengths.map(|wavelength| {
+            if is_inside {
+                airy_reflectance(cos_i, [base_ior, self.ior, 1.0], self.thickness, wavelength)
+            } else {
+                airy_reflectance(cos_i, [1.0, self.ior, base_ior], self.thickness, wavelength)
+            }
+        });
+
+        Color::rgb(r, g, b)
+    }
+
+    /// Reflectance of the film over a surface reflecting `f0` at normal incidence, such as a metal.
+    /// The surface is stood in for by the dielectric with the same reflectance head on
+    pub fn get_conductor_reflectance(&self, cos_i: f32, f0: &Color) -> Color {
+        let base_ior = |f0: f32| {
+            let root = f0.clamp(0.0, 0.99).sqrt();
+            (1.0 + root) / (1.0 - root)
+        };
+
+        let [r, g, b] = [(f0.r, self.wavelengths[0]), (f0.g, self.wavelengths[1]), (f0.b, self.wavelengths[2])]
+            .map(|(f0, wavelength)| airy_reflectance(cos_i, [1.0, self.ior, base_ior(f0)], self.thickness, wavelength));
+
+        Color::rgb(r, g, b)
+    }
+}
+
+/// Reflectance of unpolarized light off a film between two media, summing every reflection inside the film (Airy).
+/// `iors` are those of the side the light comes from, the film, and the far side
+fn airy_reflectance(cos_i: f32, [n1, n2, n3]: [f32; 3], thickness: f32, wavelength: f32) -> f32 {
+    let cos_1 = cos_i.clamp(0.0, 1.0);
+    let sin2_1 = 1.0 - cos_1 * cos_1;
+
+    // Snell's law through both interfaces, total internal reflection at either reflects everything
+    let sin2_2 = sin2_1 * (n1 / n2).powi(2);
+    let sin2_3 = sin2_1 * (n1 / n3).powi(2);
+    if sin2_2 >= 1.0 || sin2_3 >= 1.0 { return 1.0; }
+    let (cos_2, cos_3) = ((1.0 - sin2_2).sqrt(), (1.0 - sin2_3).sqrt());
+
+    // Phase difference picked up by a round trip through the film
+    let phase = 4.0 * PI * n2 * thickness * cos_2 / wavelength;
+
+    let airy = |r12: f32, r23: f32| {
+        let interference = 2.0 * r12 * r23 * phase.cos();
+        (r12 * r12 + r23 * r23 + interference) / (1.0 + r12 * r12 * r23 * r23 + interference)
+    };
+
+    let perpendicular = airy(
+        (n1 * cos_1 - n2 * cos_2) / (n1 * cos_1 + n2 * cos_2),
+        (n2 * cos_2 - n3 * cos_3) / (n2 * cos_2 + n3 * cos_3)
+    );
+    let parallel = airy(
+        (n2 * cos_1 - n1 * cos_2) / (n2 * cos_1 + n1 * cos_2),
+        (n3 * cos_2 - n2 * cos_3) / (n3 * cos_2 + n2 * cos_3)
+    );
+
+    (0.5 * (perpendicular + parallel)).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bsdf::fresnel_dielectric;
+
+    #[test]
+    fn vanishing_films_change_nothing() {
+        let film = ThinFilm::new(0.0, 1.33);
+
+        for cos_i in [1.0, 0.7, 0.3] {
+            let reflectance = film.get_reflectance(cos_i, 1.5, false);
+            assert!((reflectance.r - fresnel_dielectric(cos_i, 1.5)).abs() < 1e-4);
+
+            // The same as seen from inside the base
+            let reflectance = film.get_reflectance(cos_i, 1.5, true);
+            assert!((reflectance.g - fresnel_dielectric(cos_i, 1.0 / 1.5)).abs() < 1e-4);
+
+            // Metals with the reflectance of glass act like glass
+            let reflectance = film.get_conductor_reflectance(cos_i, &Color::rgb(0.04, 0.04, 0.04));
+            assert!((reflectance.b - fresnel_dielectric(cos_i, 1.5)).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn films_interfere() {
+        // A free-standing soap bubble in air only reflects as much as it does because of interference
+        let film = ThinFilm::new(250.0, 1.33);
+        let reflectance = film.get_reflectance(1.0, 1.0, false);
+
+        for value in [reflectance.r, reflectance.g, reflectance.b] {
+            assert!((0.0..=1.0).contains(&value));
+        }
+        assert!(reflectance.b - reflectance.r > 0.03);
+
+        // A quarter wave coating cancels reflections at its wavelength
+        let coating = ThinFilm::new(532.0 / (4.0 * 1.5_f32.sqrt()), 1.5_f32.sqrt());
+        assert!(coating.get_reflectance(1.0, 1.5, false).g < 1e-4);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,488 @@
+use crate::{
+    shapes::Hit,
+    util::{Aabb, Ray, vec::*}
+};
+
+/// Amount of buckets candidate split planes are evaluated at per axis
+const BIN_COUNT: usize = 12;
+/// Leaves may hold more primitives than this only when they can't be split
+const MAX_LEAF_SIZE: usize = 4;
+/// Cost of visiting a node relative to intersecting a primitive
+const TRAVERSAL_COST: f32 = 1.0;
+/// How much worse than freshly built a refitted hierarchy may get before it should be rebuilt
+pub const REBUILD_THRESHOLD: f32 = 1.5;
+
+#[derive(Debug, Clone, Copy)]
+struct BvhNode {
+    bounds: Aabb,
+    /// Index of the left child for interior nodes, the right child always follows it.
+    /// Index into `Bvh::indices` of the first primitive for leaves
+    first: usize,
+    /// Amount of primitives in a leaf, 0 for interior nodes
+    count: usize,
+    /// Axis the children were split on
+    axis: usize
+}
+
+impl BvhNode {
+    fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
+}
+
+/// Bounding volume hierarchy built with the surface area heuristic.
+/// Primitives are referred to by their index in the slice of bounds it was built from
+#[derive(Debug, Clone, Default)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    indices: Vec<usize>,
+    /// Amount of bounds the hierarchy was built from
+    primitive_count: usize,
+    /// Value of [Bvh::get_cost] right after building
+    built_cost: f32,
+    /// Nodes with this many primitives or fewer are never split
+    leaf_size: usize
+}
+
+impl Bvh {
+    /// Builds the hierarchy over primitives with the given bounds.
+    /// Primitives with empty bounds can never be hit and are left out
+    pub fn new(bounds: &[Aabb]) -> Self {
+        Self::with_leaf_size(bounds, 1)
+    }
+
+    /// Builds the hierarchy without splitting nodes of `leaf_size` primitives or fewer.
+    /// Bigger leaves make for a much smaller tree over huge amounts of cheap primitives
+    pub fn with_leaf_size(bounds: &[Aabb], leaf_size: usize) -> Self {
+        let indices: Vec<usize> = (0..bounds.len())
+            .filter(|&i| !bounds[i].is_empty())
+            .collect();
+
+        if indices.is_empty() {
+            return Self { primitive_count: bounds.len(), ..Default::default() };
+        }
+
+        let centroids: Vec<Vec3> = bounds.iter().map(Aabb::get_centroid).collect();
+        let root = BvhNode {
+            bounds: Aabb::empty(),
+            first: 0,
+            count: indices.len(),
+            axis: 0
+        };
+
+        let leaf_size = leaf_size.max(1);
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(indices.len().div_ceil(leaf_size) * 2),
+            indices,
+            primitive_count: bounds.len(),
+            built_cost: 0.0,
+            leaf_size
+        };
+
+        bvh.nodes.push(root);
+        bvh.subdivide(0, bounds, &centroids);
+        bvh.nodes.shrink_to_fit();
+        bvh.built_cost = bvh.get_cost();
+        bvh
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Bounds of everything in the hierarchy
+    pub fn get_bounds(&self) -> Aabb {
+        self.nodes.first().map_or(Aabb::empty(), |root| root.bounds)
+    }
+
+    /// Amount of bounds the hierarchy was built from
+    pub fn get_primitive_count(&self) -> usize {
+        self.primitive_count
+    }
+
+    /// Expected cost of tracing a ray through the hierarchy according
+    /// to the surface area heuristic, relative to intersecting one primitive
+    pub fn get_cost(&self) -> f32 {
+        let root_area = self.get_bounds().get_surface_area();
+        if root_area <= 0.0 { return 0.0; }
+
+        self.nodes
+            .iter()
+            .map(|node| {
+                let cost = if node.is_leaf() { node.count as f32 } else { TRAVERSAL_COST };
+                cost * node.bounds.get_surface_area() / root_area
+            })
+            .sum()
+    }
+
+    /// How much worse the hierarchy has become through refitting, 1.0 meaning not at all
+    pub fn get_degradation(&self) -> f32 {
+        if self.built_cost <= 0.0 { return 1.0; }
+        self.get_cost() / self.built_cost
+    }
+
+    /// Whether refitting has degraded the hierarchy past [REBUILD_THRESHOLD]
+    pub fn needs_rebuild(&self) -> bool {
+        self.get_degradation() > REBUILD_THRESHOLD
+    }
+
+    /// Whether [Bvh::refit] can bring the hierarchy up to date with `bounds`.
+    /// Refitting can't add primitives, so the same ones need to be left out for having empty bounds
+    pub fn can_refit(&self, bounds: &[Aabb]) -> bool {
+        bounds.len() == self.primitive_count
+            && bounds.iter().filter(|bounds| !bounds.is_empty()).count() == self.indices.len()
+            && self.indices.iter().all(|&i| !bounds[i].is_empty())
+    }
+
+    /// Updates the bounds of every node after primitives moved, keeping the tree layout.
+    /// `bounds` must describe the same primitives, in the same order, the hierarchy was built from
+    pub fn refit(&mut self, bounds: &[Aabb]) {
+        assert_eq!(bounds.len(), self.primitive_count, "Refitting needs the same amount of primitives");
+
+        // Children are always stored after their parent,
+        // so walking backwards updates them before the parent reads them
+        for node_index in (0..self.nodes.len()).rev() {
+            let node = self.nodes[node_index];
+
+            self.nodes[node_index].bounds = if node.is_leaf() {
+                self.indices[node.first..node.first + node.count]
+                    .iter()
+                    .fold(Aabb::empty(), |total, &i| total.union(&bounds[i]))
+            } else {
+                self.nodes[node.first].bounds.union(&self.nodes[node.first + 1].bounds)
+            };
+        }
+    }
+
+    fn subdivide(&mut self, node_index: usize, bounds: &[Aabb], centroids: &[Vec3]) {
+        let BvhNode { first, count, .. } = self.nodes[node_index];
+        let primitives = &self.indices[first..first + count];
+
+        let node_bounds = primitives
+            .iter()
+            .fold(Aabb::empty(), |total, &i| total.union(&bounds[i]));
+
+        self.nodes[node_index].bounds = node_bounds;
+        if count <= self.leaf_size { return; }
+
+        let centroid_bounds = primitives
+            .iter()
+            .fold(Aabb::empty(), |total, &i| total.grow(&centroids[i]));
+
+        let split = find_split(primitives, bounds, centroids, &centroid_bounds);
+        let leaf_cost = count as f32 * node_bounds.get_surface_area();
+        let max_leaf_size = self.leaf_size.max(MAX_LEAF_SIZE);
+
+        // Split position along the axis, or `None` to halve the primitive list
+        let (axis, position) = match split {
+            Some((axis, position, cost)) => {
+                let split_cost = TRAVERSAL_COST * node_bounds.get_surface_area() + cost;
+                if split_cost >= leaf_cost && count <= max_leaf_size { return; }
+                (axis, Some(position))
+            },
+            None if count <= max_leaf_size => return,
+            None => (centroid_bounds.get_longest_axis(), None)
+        };
+
+        let primitives = &mut self.indices[first..first + count];
+        let mut left_count = match position {
+            Some(position) => partition(primitives, |&i| get_axis(&centroids[i], axis) < position),
+            None => count / 2
+        };
+
+        // Every centroid fell on one side of the plane
+        if left_count == 0 || left_count == count {
+            left_count = count / 2;
+        }
+
+        let left = self.nodes.len();
+        self.nodes.push(BvhNode { bounds: Aabb::empty(), first, count: left_count, axis: 0 });
+        self.nodes.push(BvhNode { bounds: Aabb::empty(), first: first + left_count, count: count - left_count, axis: 0 });
+
+        self.nodes[node_index] = BvhNode {
+            bounds: node_bounds,
+            first: left,
+            count: 0,
+            axis
+        };
+
+        self.subdivide(left, bounds, centroids);
+        self.subdivide(left + 1, bounds, centroids);
+    }
+
+    /// Find the closest primitive the ray hits, `intersect` is called with
+    /// the index of every primitive whose bounds the ray passes through
+    pub fn intersect<F>(&self, ray: &Ray, mut intersect: F) -> Option<(usize, Hit)>
+    where
+        F: FnMut(usize) -> Option<Hit>
+    {
+        if self.nodes.is_empty() { return None; }
+
+        let inverse_direction = ray.get_inverse_direction();
+        let direction_is_negative = [
+            ray.direction.x < 0.0,
+            ray.direction.y < 0.0,
+            ray.direction.z < 0.0
+        ];
+
+        let mut closest: Option<(usize, Hit)> = None;
+        let mut closest_distance = f32::INFINITY;
+        let mut stack: Vec<usize> = Vec::with_capacity(64);
+        stack.push(0);
+
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+
+            // Skip nodes which are missed or start further away than what was already hit
+            if node.bounds.intersect(&ray.position, &inverse_direction, closest_distance).is_none() {
+                continue;
+            }
+
+            if node.is_leaf() {
+                for &primitive in &self.indices[node.first..node.first + node.count] {
+                    let Some(hit) = intersect(primitive) else { continue; };
+
+                    if hit.distance < closest_distance {
+                        closest_distance = hit.distance;
+                        closest = Some((primitive, hit));
+                    }
+                }
+                continue;
+            }
+
+            // Visit the child on the side the ray comes from first,
+            // so closer hits are found early and cull the farther child
+            if direction_is_negative[node.axis] {
+                stack.push(node.first);
+                stack.push(node.first + 1);
+            } else {
+                stack.push(node.first + 1);
+                stack.push(node.first);
+            }
+        }
+
+        closest
+    }
+
+    /// Calls `visit` with the index of every primitive whose bounds
+    /// the ray passes through before `max_distance`, in no particular order
+    pub fn intersect_all<F>(&self, ray: &Ray, max_distance: f32, mut visit: F)
+    where
+        F: FnMut(usize)
+    {
+        if self.nodes.is_empty() { return; }
+
+        let inverse_direction = ray.get_inverse_direction();
+        let mut stack: Vec<usize> = Vec::with_capacity(64);
+        stack.push(0);
+
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+
+            if node.bounds.intersect(&ray.position, &inverse_direction, max_distance).is_none() {
+                continue;
+            }
+
+            if node.is_leaf() {
+                self.indices[node.first..node.first + node.count]
+                    .iter()
+                    .for_each(|&primitive| visit(primitive));
+                continue;
+            }
+
+            stack.push(node.first);
+            stack.push(node.first + 1);
+        }
+    }
+}
+
+/// Evaluate the surface area heuristic at evenly spaced planes along every axis.
+/// Returns the axis, position and cost of the cheapest split, if any
+fn find_split(
+    primitives: &[usize],
+    bounds: &[Aabb],
+    centroids: &[Vec3],
+    centroid_bounds: &Aabb
+) -> Option<(usize, f32, f32)> {
+    let mut best: Option<(usize, f32, f32)> = None;
+
+    for axis in 0..3 {
+        let min = get_axis(&centroid_bounds.min, axis);
+        let extent = get_axis(&centroid_bounds.max, axis) - min;
+        if extent <= 0.0 { continue; }
+
+        let scale = BIN_COUNT as f32 / extent;
+        let mut bins = [(Aabb::empty(), 0usize); BIN_COUNT];
+
+        for &i in primitives {
+            let bin = (((get_axis(&centroids[i], axis) - min) * scale) as usize).min(BIN_COUNT - 1);
+            bins[bin].0 = bins[bin].0.union(&bounds[i]);
+            bins[bin].1 += 1;
+        }
+
+        // Sweep from both ends to get the cost of splitting after every bin
+        let mut left_costs = [0.0; BIN_COUNT - 1];
+        let mut left_bounds = Aabb::empty();
+        let mut left_count = 0;
+
+        for (i, cost) in left_costs.iter_mut().enumerate() {
+            left_bounds = left_bounds.union(&bins[i].0);
+            left_count += bins[i].1;
+            *cost = left_count as f32 * left_bounds.get_surface_area();
+        }
+
+        let mut right_bounds = Aabb::empty();
+        let mut right_count = 0;
+
+        for i in (1..BIN_COUNT).rev() {
+            right_bounds = right_bounds.union(&bins[i].0);
+            right_count += bins[i].1;
+
+            let cost = left_costs[i - 1] + right_count as f32 * right_bounds.get_surface_area();
+            if best.is_none_or(|(_, _, best_cost)| cost < best_cost) {
+                best = Some((axis, min + i as f32 / scale, cost));
+            }
+        }
+    }
+
+    best
+}
+
+/// Reorders `values` so every value matching `predicate` comes first,
+/// returns how many matched
+fn partition<T, F: Fn(&T) -> bool>(values: &mut [T], predicate: F) -> usize {
+    let mut matched = 0;
+
+    for i in 0..values.len() {
+        if predicate(&values[i]) {
+            values.swap(i, matched);
+            matched += 1;
+        }
+    }
+
+    matched
+}
+
+fn get_axis(vector: &Vec3, axis: usize) -> f32 {
+    match axis {
+        0 => vector.x,
+        1 => vector.y,
+        _ => vector.z
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{shapes::{Shape, Sphere}, util::Material};
+
+    fn grid_of_spheres() -> Vec<Sphere> {
+        (0..1000)
+            .map(|i| {
+                let position = Vec3::new((i % 10) as f32, ((i / 10) % 10) as f32, (i / 100) as f32);
+                Sphere::new(position.mul_by(1.5), 0.2 + (i % 7) as f32 * 0.1, Material::default())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let spheres = grid_of_spheres();
+        let bounds: Vec<Aabb> = spheres.iter().map(Sphere::get_bounds).collect();
+        let bvh = Bvh::new(&bounds);
+
+        for i in 0..500 {
+            let angle = i as f32 * 0.37;
+            let ray = Ray::new(
+                Vec3::new(-5.0 + (i % 13) as f32, 20.0 - (i % 17) as f32, -10.0),
+                Vec3::new(angle.cos(), angle.sin() * 0.5, 1.0)
+            );
+
+            let expected = spheres
+                .iter()
+                .enumerate()
+                .filter_map(|(index, sphere)| sphere.intersect(&ray).map(|hit| (index, hit.distance)))
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+
+            let found = bvh
+                .intersect(&ray, |index| spheres[index].intersect(&ray))
+                .map(|(index, hit)| (index, hit.distance));
+
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn refit_follows_moved_shapes() {
+        let mut spheres = grid_of_spheres();
+        let bounds: Vec<Aabb> = spheres.iter().map(Sphere::get_bounds).collect();
+        let mut bvh = Bvh::new(&bounds);
+        assert!((bvh.get_degradation() - 1.0).abs() < 1e-4);
+
+        // Scatter the spheres so the original grouping stops making sense
+        for (i, sphere) in spheres.iter_mut().enumerate() {
+            let offset = ((i * 7919) % 1000) as f32 * 0.02;
+            sphere.position = Vec3::new(sphere.position.z + offset, sphere.position.x, sphere.position.y - offset);
+        }
+
+        let bounds: Vec<Aabb> = spheres.iter().map(Sphere::get_bounds).collect();
+        bvh.refit(&bounds);
+        assert!(bvh.needs_rebuild());
+
+        let ray = Ray::new(Vec3::new(5.0, 5.0, -30.0), Vec3::new(0.05, 0.1, 1.0));
+        let expected = spheres
+            .iter()
+            .enumerate()
+            .filter_map(|(index, sphere)| sphere.intersect(&ray).map(|hit| (index, hit.distance)))
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+
+        let found = bvh
+            .intersect(&ray, |index| spheres[index].intersect(&ray))
+            .map(|(index, hit)| (index, hit.distance));
+
+        assert!(expected.is_some());
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn splits_sparse_primitives() {
+        // Two far apart clusters leave most bins along x empty
+        let spheres: Vec<Sphere> = (0..250)
+            .map(|i| {
+                let position = Vec3::new((i % 5) as f32 + (i / 125) as f32 * 100.0, ((i * 37) % 125) as f32 * 0.08, ((i * 91) % 125) as f32 * 0.08);
+                Sphere::new(position, 0.05, Material::default())
+            })
+            .collect();
+        let bounds: Vec<Aabb> = spheres.iter().map(Sphere::get_bounds).collect();
+        let bvh = Bvh::new(&bounds);
+
+        // The first split separates the clusters
+        let root = bvh.nodes[0];
+        for child in &bvh.nodes[root.first..root.first + 2] {
+            assert!(child.bounds.get_extent().x < 10.0, "{:?}", child.bounds);
+        }
+
+        let target = &spheres[137];
+        let ray = Ray::new(target.position.sub(&Vec3::new(0.0, 0.0, 20.0)), Vec3::new(0.0, 0.0, 1.0));
+        let (index, hit) = bvh.intersect(&ray, |index| spheres[index].intersect(&ray)).unwrap();
+        assert_eq!(index, 137);
+        assert!((hit.distance - 19.95).abs() < 1e-4);
+    }
+
+    #[test]
+    fn skips_empty_bounds() {
+        let bvh = Bvh::new(&[Aabb::empty(), Aabb::empty()]);
+        assert!(bvh.is_empty());
+
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(bvh.intersect(&ray, |_| unreachable!()).is_none());
+
+        // Primitives that gain or lose their bounds need a rebuild to be found
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, 5.0), 1.0, Material::default()).get_bounds();
+        assert!(!bvh.can_refit(&[Aabb::empty(), sphere]));
+        let bvh = Bvh::new(&[Aabb::empty(), sphere]);
+        assert!(bvh.can_refit(&[Aabb::empty(), sphere.pad(1.0)]));
+        assert!(!bvh.can_refit(&[sphere, Aabb::empty()]));
+        assert!(!bvh.can_refit(&[Aabb::empty(), Aabb::empty()]));
+    }
+}
\ No newline at end of file
@@ -48,6 +48,11 @@ impl Camera {
         &self.rays
     }
 
+    /// Angle between the rays of neighbouring pixels, the image plane being 2.0 tall at a distance of 1.0
+    pub fn get_spread_angle(&self, height: u32) -> f32 {
+        2.0 / height as f32
+    }
+
     pub fn set_position(mut self, position: Vec3) -> Self {
         self.position = position;
         self
@@ -2,6 +2,8 @@ use crate::util::vec::*;
 
 pub trait Light: 'static {
     fn get_intensity(&self, normal: &Vec3) -> f32;
+    /// Direction from `position` towards the light
+    fn get_direction(&self, position: &Vec3) -> Vec3;
 }
 
 #[derive(Clone, Copy)]
@@ -15,6 +17,10 @@ impl Light for PointLight {
     fn get_intensity(&self, normal: &Vec3) -> f32 {
         normal.dot(&self.direction).max(0.0) * self.intensity
     }
+
+    fn get_direction(&self, _position: &Vec3) -> Vec3 {
+        self.direction
+    }
 }
 
 impl PointLight {
@@ -0,0 +1,546 @@
+use std::f32::consts::PI;
+
+use crate::{
+    shapes::Shape,
+    util::{Aabb, Color, Ray, Wavelengths, random, vec::*}
+};
+
+/// Boundary crossings followed along a ray before giving up on a shape bounding a medium
+const MAX_CROSSINGS: usize = 16;
+/// Tentative collisions after which light stops being tracked through media,
+/// high enough for sparse grids with a large majorant
+const MAX_COLLISIONS: usize = 65536;
+/// Distance rays are pushed past a boundary so they don't hit it again right away
+const OFFSET: f32 = 0.0001;
+
+/// Henyey–Greenstein phase function, spreading light scattered inside a medium around its direction of travel
+#[derive(Debug, Clone, Copy)]
+pub struct HenyeyGreenstein {
+    /// Mean cosine of the scattering angle, from -1 (backwards) through 0 (isotropic) to 1 (forwards)
+    pub g: f32
+}
+
+impl HenyeyGreenstein {
+    pub fn new(g: f32) -> Self {
+        Self { g: g.clamp(-0.99, 0.99) }
+    }
+
+    /// Density per solid angle of light travelling along `direction` being scattered into `scattered`
+    pub fn evaluate(&self, direction: &Vec3, scattered: &Vec3) -> f32 {
+        let cos_theta = direction.dot(scattered);
+        let denominator = 1.0 + self.g * self.g - 2.0 * self.g * cos_theta;
+
+        (1.0 - self.g * self.g) / (4.0 * PI * denominator * denominator.max(1e-8).sqrt())
+    }
+
+    /// Picks a new direction of travel for light travelling along `direction`, proportionally to [HenyeyGreenstein::evaluate]
+    pub fn sample(&self, direction: &Vec3) -> Vec3 {
+        let (u1, u2) = (random(), random());
+
+        let cos_theta = if self.g.abs() < 1e-3 {
+            1.0 - 2.0 * u1
+        } else {
+            let term = (1.0 - self.g * self.g) / (1.0 - self.g + 2.0 * self.g * u1);
+            (1.0 + self.g * self.g - term * term) / (2.0 * self.g)
+        };
+
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+
+        // Any two directions perpendicular to the current one will do
+        let helper = if direction.x.abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+        let tangent = direction.cross(&helper).normalize();
+        let bitangent = direction.cross(&tangent);
+
+        tangent.mul_by(sin_theta * phi.cos())
+            .add(&bitangent.mul_by(sin_theta * phi.sin()))
+            .add(&direction.mul_by(cos_theta))
+            .normalize()
+    }
+}
+
+/// Densities stored on a regular grid over a box, blended trilinearly between cells
+#[derive(Debug, Clone)]
+pub struct DensityGrid {
+    bounds: Aabb,
+    /// Number of samples along x, y and z
+    resolution: [usize; 3],
+    /// Samples ordered x first, then y, then z
+    values: Vec<f32>,
+    max: f32
+}
+
+impl DensityGrid {
+    /// Panics if `values` doesn't hold one sample for every point of the grid
+    pub fn new(bounds: Aabb, resolution: [usize; 3], values: Vec<f32>) -> Self {
+        assert_eq!(values.len(), resolution.iter().product::<usize>(), "Every grid point needs a density");
+        assert!(resolution.iter().all(|&count| count > 0), "Grids need at least one sample per axis");
+
+        let max = values.iter().fold(0.0f32, |max, &value| max.max(value));
+        Self { bounds, resolution, values, max }
+    }
+
+    pub fn get_bounds(&self) -> Aabb {
+        self.bounds
+    }
+
+    /// Highest density anywhere in the grid
+    pub fn get_max(&self) -> f32 {
+        self.max
+    }
+
+    /// Density at `point`, zero outside of the grid
+    pub fn get_density(&self, point: &Vec3) -> f32 {
+        let size = self.bounds.max.sub(&self.bounds.min);
+        let local = point.sub(&self.bounds.min);
+        let relative = [local.x / size.x, local.y / size.y, local.z / size.z];
+
+        if relative.iter().any(|value| !(0.0..=1.0).contains(value)) { return 0.0; }
+
+        // Grid coordinates of the point, and the cell corner below it
+        let mut lower = [0; 3];
+        let mut fraction = [0.0; 3];
+        for axis in 0..3 {
+            let coordinate = relative[axis] * (self.resolution[axis] - 1) as f32;
+            lower[axis] = (coordinate as usize).min(self.resolution[axis].saturating_sub(2));
+            fraction[axis] = (coordinate - lower[axis] as f32).clamp(0.0, 1.0);
+        }
+
+        let mut density = 0.0;
+        for corner in 0..8 {
+            let mut weight = 1.0;
+            let mut index = [0; 3];
+
+            for axis in 0..3 {
+                let upper = (corner >> axis) & 1 == 1;
+                index[axis] = (lower[axis] + upper as usize).min(self.resolution[axis] - 1);
+                weight *= if upper { fraction[axis] } else { 1.0 - fraction[axis] };
+            }
+
+            density += weight * self.get_value(index);
+        }
+
+        density
+    }
+
+    fn get_value(&self, [x, y, z]: [usize; 3]) -> f32 {
+        self.values[x + self.resolution[0] * (y + self.resolution[1] * z)]
+    }
+}
+
+/// How the density of a medium changes through space
+#[derive(Debug, Clone)]
+pub enum Density {
+    /// The same everywhere
+    Homogeneous,
+    /// Looked up in a grid, and zero outside of it
+    Grid(DensityGrid)
+}
+
+/// Participating medium such as fog, smoke or murky water, that absorbs and scatters light along rays
+pub struct Medium {
+    /// Light absorbed per unit of distance at unit density
+    pub absorption: Color,
+    /// Light scattered per unit of distance at unit density
+    pub scattering: Color,
+    pub phase: HenyeyGreenstein,
+    pub density: Density,
+    /// Closed shape the medium fills, it fills all of space when `None`
+    pub boundary: Option<Box<dyn Shape>>
+}
+
+impl Medium {
+    /// Medium with the same density everywhere, e.g. fog filling the whole scene
+    pub fn homogeneous(absorption: Color, scattering: Color) -> Self {
+        Self {
+            absorption,
+            scattering,
+            phase: HenyeyGreenstein::new(0.0),
+            density: Density::Homogeneous,
+            boundary: None
+        }
+    }
+
+    /// Medium whose coefficients are scaled by densities from `grid`, e.g. smoke
+    pub fn heterogeneous(absorption: Color, scattering: Color, grid: DensityGrid) -> Self {
+        Self {
+            density: Density::Grid(grid),
+            ..Self::homogeneous(absorption, scattering)
+        }
+    }
+
+    /// Confines the medium to the inside of `shape`.
+    /// The boundary is never drawn, so cutouts in its material don't open it up
+    pub fn set_boundary<S: Shape>(mut self, shape: S) -> Self {
+        self.boundary = Some(Box::new(shape));
+        self
+    }
+
+    /// Sets how much light keeps travelling forwards when scattered, see [HenyeyGreenstein]
+    pub fn set_anisotropy(mut self, g: f32) -> Self {
+        self.phase = HenyeyGreenstein::new(g);
+        self
+    }
+
+    /// Light scattered per unit of distance at unit density, at the given wavelengths in spectral rendering
+    pub fn get_scattering(&self, wavelengths: Option<&Wavelengths>) -> Color {
+        wavelengths.map_or(self.scattering, |wavelengths| wavelengths.from_rgb(&self.scattering))
+    }
+
+    /// Light absorbed or scattered per unit of distance at unit density, at the given wavelengths in spectral rendering
+    pub fn get_extinction(&self, wavelengths: Option<&Wavelengths>) -> Color {
+        let extinction = self.absorption.add(&self.scattering);
+        wavelengths.map_or(extinction, |wavelengths| wavelengths.from_rgb(&extinction))
+    }
+
+    /// Upper bound of the extinction of every channel anywhere in the medium
+    pub fn get_majorant(&self, wavelengths: Option<&Wavelengths>) -> f32 {
+        let extinction = self.get_extinction(wavelengths);
+        let max_density = match &self.density {
+            Density::Homogeneous => 1.0,
+            Density::Grid(grid) => grid.get_max()
+        };
+
+        extinction.r.max(extinction.g).max(extinction.b) * max_density
+    }
+
+    /// Density at `point`, ignoring the boundary
+    pub fn get_density(&self, point: &Vec3) -> f32 {
+        match &self.density {
+            Density::Homogeneous => 1.0,
+            Density::Grid(grid) => grid.get_density(point)
+        }
+    }
+
+    /// Distances along `ray`, which must have a unit direction, between which it travels through the medium
+    pub fn get_intervals(&self, ray: &Ray, max_distance: f32) -> Vec<(f32, f32)> {
+        let mut intervals = match &self.boundary {
+            Some(shape) => get_inside_intervals(shape.as_ref(), ray, max_distance),
+            None => vec![(0.0, max_distance)]
+        };
+
+        // Grids have no density outside their box
+        if let Density::Grid(grid) = &self.density {
+            let Some((enter, exit)) = clip_to_box(&grid.get_bounds(), ray) else { return Vec::new() };
+
+            intervals = intervals
+                .into_iter()
+                .map(|(start, end)| (start.max(enter), end.min(exit)))
+                .filter(|(start, end)| start < end)
+                .collect();
+        }
+
+        intervals
+    }
+
+    /// Fraction of the light that makes it `max_distance` along `ray` through the medium.
+    /// Exact for homogeneous media, estimated with ratio tracking for grids
+    pub fn get_transmittance(&self, ray: &Ray, max_distance: f32, wavelengths: Option<&Wavelengths>) -> Color {
+        let direction = ray.direction.normalize();
+        let ray = Ray::new(ray.position, direction);
+        let intervals = self.get_intervals(&ray, max_distance);
+        let extinction = self.get_extinction(wavelengths);
+
+        if let Density::Homogeneous = self.density {
+            let length: f32 = intervals.iter().map(|(start, end)| end - start).sum();
+
+            return Color::rgb(
+                (-extinction.r * length).exp(),
+                (-extinction.g * length).exp(),
+                (-extinction.b * length).exp()
+            );
+        }
+
+        let majorant = self.get_majorant(wavelengths);
+        let mut transmittance = Color::rgb(1.0, 1.0, 1.0);
+        if majorant <= 0.0 { return transmittance; }
+
+        for (start, end) in intervals {
+            let mut distance = start;
+
+            loop {
+                distance -= (1.0 - random()).ln() / majorant;
+                if distance >= end { break; }
+
+                // Every tentative collision lets through the part of the light that was a null collision
+                let sigma = extinction.mul_by(self.get_density(&ray.get_point(distance)));
+                transmittance.mul_mut(&Color::rgb(
+                    1.0 - sigma.r / majorant,
+                    1.0 - sigma.g / majorant,
+                    1.0 - sigma.b / majorant
+                ));
+
+                if transmittance.r.max(transmittance.g).max(transmittance.b) <= 0.0 { return transmittance; }
+            }
+        }
+
+        transmittance
+    }
+}
+
+/// Finds where light travelling along `ray` gets scattered by any of the `media` before `max_distance`,
+/// with spectral delta tracking. `throughput` is weighted by the light absorbed or let through on the way.
+/// Returns the point, the index of the medium that scattered the light and how far along the normalized
+/// ray the point is, `None` if it travelled on
+pub fn sample_media(
+    ray: &Ray,
+    max_distance: f32,
+    media: &[Medium],
+    wavelengths: Option<&Wavelengths>,
+    throughput: &mut Color
+) -> Option<(Vec3, usize, f32)> {
+    let ray = Ray::new(ray.position, ray.direction.normalize());
+
+    let intervals: Vec<Vec<(f32, f32)>> = media
+        .iter()
+        .map(|medium| medium.get_intervals(&ray, max_distance))
+        .collect();
+
+    // A single majorant for all of the media overlapping the ray keeps the tracking simple
+    let majorant: f32 = media
+        .iter()
+        .zip(&intervals)
+        .filter(|(_, intervals)| !intervals.is_empty())
+        .map(|(medium, _)| medium.get_majorant(wavelengths))
+        .sum();
+    if majorant <= 0.0 { return None; }
+
+    let start = intervals.iter().flatten().map(|&(start, _)| start).fold(f32::INFINITY, f32::min);
+    let end = intervals.iter().flatten().map(|&(_, end)| end).fold(0.0, f32::max);
+
+    let mut distance = start;
+    for _ in 0..MAX_COLLISIONS {
+        distance -= (1.0 - random()).ln() / majorant;
+        if distance >= end { return None; }
+
+        let point = ray.get_point(distance);
+
+        // Coefficients of every medium the point is inside of
+        let mut scattering = vec![Color::rgb(0.0, 0.0, 0.0); media.len()];
+        let mut total_scattering = Color::rgb(0.0, 0.0, 0.0);
+        let mut extinction = Color::rgb(0.0, 0.0, 0.0);
+
+        for (index, medium) in media.iter().enumerate() {
+            let is_inside = intervals[index].iter().any(|&(start, end)| (start..end).contains(&distance));
+            if !is_inside { continue; }
+
+            let density = medium.get_density(&point);
+            scattering[index] = medium.get_scattering(wavelengths).mul_by(density);
+            total_scattering.add_mut(&scattering[index]);
+            extinction.add_mut(&medium.get_extinction(wavelengths).mul_by(density));
+        }
+
+        let null = Color::rgb(
+            (majorant - extinction.r).max(0.0),
+            (majorant - extinction.g).max(0.0),
+            (majorant - extinction.b).max(0.0)
+        );
+
+        // Absorption is accounted for by weighting, so collisions either scatter or are null.
+        // Both are chosen by how much light they carry, averaged over the channels
+        let scatter_weight = average(&total_scattering.mul(throughput));
+        let null_weight = average(&null.mul(throughput));
+        let total = scatter_weight + null_weight;
+        // Nothing is left to carry
+        if total <= 0.0 {
+            *throughput = Color::rgb(0.0, 0.0, 0.0);
+            return None;
+        }
+
+        if random() * total < scatter_weight {
+            throughput.mul_mut(&total_scattering.mul_by(total / (majorant * scatter_weight)));
+
+            // The medium that scatters is picked proportionally to how much it scatters
+            let mut target = random() * average(&total_scattering);
+            let index = (0..media.len())
+                .find(|&index| {
+                    target -= average(&scattering[index]);
+                    target <= 0.0 && average(&scattering[index]) > 0.0
+                })
+                .unwrap_or_else(|| (0..media.len()).rev().find(|&index| average(&scattering[index]) > 0.0).unwrap_or(0));
+
+            return Some((point, index, distance));
+        }
+
+        throughput.mul_mut(&null.mul_by(total / (majorant * null_weight)));
+    }
+
+    // Darkening light that still carries something would bias the image,
+    // so it carries on through the rest of the media as is
+    None
+}
+
+/// Fraction of the light that makes it `max_distance` along `ray` through all of the `media`
+pub fn get_transmittance(ray: &Ray, max_distance: f32, media: &[Medium], wavelengths: Option<&Wavelengths>) -> Color {
+    media
+        .iter()
+        .fold(Color::rgb(1.0, 1.0, 1.0), |transmittance, medium| {
+            transmittance.mul(&medium.get_transmittance(ray, max_distance, wavelengths))
+        })
+}
+
+fn average(color: &Color) -> f32 {
+    (color.r + color.g + color.b) / 3.0
+}
+
+/// Distances along `ray` between which it is inside the closed `shape`.
+/// Every crossing counts, as the volume stays closed whatever the material's opacity
+fn get_inside_intervals(shape: &dyn Shape, ray: &Ray, max_distance: f32) -> Vec<(f32, f32)> {
+    let mut intervals = Vec::new();
+    let mut entered: Option<f32> = None;
+    let mut distance = 0.0;
+
+    for _ in 0..MAX_CROSSINGS {
+        let probe = Ray::new(ray.get_point(distance), ray.direction);
+        let Some(hit) = shape.intersect(&probe) else { break };
+
+        let crossing = distance + hit.distance;
+        let is_entering = hit.is_front_face(&probe);
+        if crossing >= max_distance && is_entering { break; }
+
+        if is_entering {
+            entered = Some(crossing);
+        } else if let Some(start) = entered.take() {
+            intervals.push((start, crossing.min(max_distance)));
+        } else if intervals.is_empty() {
+            // Leaving before entering means the ray started inside
+            intervals.push((0.0, crossing.min(max_distance)));
+        }
+
+        if crossing >= max_distance { return intervals; }
+        distance = crossing + OFFSET;
+    }
+
+    if let Some(start) = entered {
+        intervals.push((start, max_distance));
+    }
+
+    intervals
+}
+
+/// Distances along `ray` between which it is inside `bounds`
+fn clip_to_box(bounds: &Aabb, ray: &Ray) -> Option<(f32, f32)> {
+    let inverse_direction = ray.get_inverse_direction();
+    let near = bounds.min.sub(&ray.position).mul(&inverse_direction);
+    let far = bounds.max.sub(&ray.position).mul(&inverse_direction);
+
+    let enter = near.x.min(far.x).max(near.y.min(far.y)).max(near.z.min(far.z)).max(0.0);
+    let exit = near.x.max(far.x).min(near.y.max(far.y)).min(near.z.max(far.z));
+
+    if enter <= exit { Some((enter, exit)) } else { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{shapes::Sphere, util::Material};
+
+    #[test]
+    fn henyey_greenstein() {
+        let direction = Vec3::new(0.0, 0.0, 1.0);
+
+        for g in [-0.5, 0.0, 0.7] {
+            let phase = HenyeyGreenstein::new(g);
+
+            // Integrates to one over the sphere, and sampled directions average to `g`
+            let count = 20000;
+            let mut integral = 0.0;
+            let mut mean_cosine = 0.0;
+            for _ in 0..count {
+                let z = 1.0 - 2.0 * random();
+                let ring = (1.0 - z * z).sqrt();
+                let phi = 2.0 * PI * random();
+                integral += phase.evaluate(&direction, &Vec3::new(ring * phi.cos(), ring * phi.sin(), z)) * 4.0 * PI;
+
+                mean_cosine += phase.sample(&direction).dot(&direction);
+            }
+
+            assert!((integral / count as f32 - 1.0).abs() < 0.05);
+            assert!((mean_cosine / count as f32 - g).abs() < 0.02);
+        }
+    }
+
+    #[test]
+    fn bounded_intervals() {
+        let medium = Medium::homogeneous(Color::rgb(0.5, 1.0, 2.0), Color::rgb(0.0, 0.0, 0.0))
+            .set_boundary(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, Material::default()));
+
+        let outside = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let intervals = medium.get_intervals(&outside, f32::INFINITY);
+        assert_eq!(intervals.len(), 1);
+        assert!((intervals[0].0 - 4.0).abs() < 1e-4 && (intervals[0].1 - 6.0).abs() < 1e-4);
+
+        let inside = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        let intervals = medium.get_intervals(&inside, 0.5);
+        assert_eq!(intervals, vec![(0.0, 0.5)]);
+
+        // Beer–Lambert over the two units spent inside
+        let transmittance = medium.get_transmittance(&outside, f32::INFINITY, None);
+        assert!((transmittance.g - (-2.0f32).exp()).abs() < 1e-4);
+        assert!((transmittance.b - (-4.0f32).exp()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn ratio_tracking() {
+        // A grid with the same density everywhere matches the exact homogeneous transmittance
+        let bounds = Aabb::from_points(&[Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0)]);
+        let grid = DensityGrid::new(bounds, [2, 2, 2], vec![0.5; 8]);
+        assert!((grid.get_density(&Vec3::new(0.3, -0.2, 0.9)) - 0.5).abs() < 1e-6);
+        assert_eq!(grid.get_density(&Vec3::new(0.0, 2.0, 0.0)), 0.0);
+
+        let medium = Medium::heterogeneous(Color::rgb(1.0, 2.0, 0.0), Color::rgb(1.0, 0.0, 0.0), grid);
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -3.0), Vec3::new(0.0, 0.0, 2.0));
+
+        let count = 4000;
+        let mut transmittance = Color::rgb(0.0, 0.0, 0.0);
+        for _ in 0..count {
+            transmittance.add_mut(&medium.get_transmittance(&ray, f32::INFINITY, None));
+        }
+        transmittance = transmittance.div_by(count as f32);
+
+        // Two units through a density of one half
+        let expected = (-2.0f32).exp();
+        assert!((transmittance.r - expected).abs() < 0.02);
+        assert!((transmittance.g - expected).abs() < 0.02);
+        assert!((transmittance.b - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn delta_tracking() {
+        // Without scattering light is only weighted by what the medium lets through
+        let media = [Medium::homogeneous(Color::rgb(0.5, 1.0, 0.25), Color::rgb(0.0, 0.0, 0.0))];
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+
+        let count = 4000;
+        let mut average = Color::rgb(0.0, 0.0, 0.0);
+        for _ in 0..count {
+            let mut throughput = Color::rgb(1.0, 1.0, 1.0);
+            assert!(sample_media(&ray, 2.0, &media, None, &mut throughput).is_none());
+            average.add_mut(&throughput);
+        }
+        average = average.div_by(count as f32);
+
+        assert!((average.r - (-1.0f32).exp()).abs() < 0.03);
+        assert!((average.g - (-2.0f32).exp()).abs() < 0.03);
+        assert!((average.b - (-0.5f32).exp()).abs() < 0.03);
+
+        // A purely scattering medium scatters exactly the light it doesn't let through
+        let media = [Medium::homogeneous(Color::rgb(0.0, 0.0, 0.0), Color::rgb(1.0, 1.0, 1.0))];
+        let scattered = (0..count)
+            .filter_map(|_| sample_media(&ray, 1.0, &media, None, &mut Color::rgb(1.0, 1.0, 1.0)))
+            .inspect(|(point, _, distance)| assert!(*distance < 1.0 && (point.x - distance).abs() < 1e-6))
+            .count();
+
+        assert!((scattered as f32 / count as f32 - (1.0 - (-1.0f32).exp())).abs() < 0.03);
+
+        // A mostly empty grid with a dense top takes thousands of null collisions to cross, losing nothing
+        let bounds = Aabb::new(Vec3::new(0.0, -1.0, -1.0), Vec3::new(20.0, 1.0, 1.0));
+        let values = (0..12).map(|i| if (i / 2) % 3 == 2 { 100.0 } else { 0.0 }).collect();
+        let media = [Medium::heterogeneous(Color::rgb(1.0, 1.0, 1.0), Color::rgb(0.0, 0.0, 0.0), DensityGrid::new(bounds, [2, 3, 2], values))];
+        let ray = Ray::new(Vec3::new(-1.0, -0.5, 0.0), Vec3::new(1.0, 0.0, 0.0));
+
+        let mut throughput = Color::rgb(1.0, 1.0, 1.0);
+        assert!(sample_media(&ray, 30.0, &media, None, &mut throughput).is_none());
+        assert!((throughput.r - 1.0).abs() < 1e-3, "{throughput:?}");
+    }
+}
\ No newline at end of file
@@ -1,3 +1,7 @@
+pub mod bvh;
 pub mod camera;
 pub mod light;
-pub mod scene;
\ No newline at end of file
+pub mod scene;
+pub mod splats;
+pub mod subsurface;
+pub mod medium;
\ No newline at end of file
@@ -1,19 +1,28 @@
 use image::Rgba;
 //use num_traits::Pow;
-use std::time::Instant;
 //use std::io;
 
 use super::{
+    bvh::Bvh,
     camera::Camera,
-    light::Light
+    light::Light,
+    medium::{HenyeyGreenstein, Medium, get_transmittance, sample_media},
+    splats::{GaussianSplats, trace_all},
+    subsurface::{Exit, random_walk}
 };
 
 use crate::{
-    shapes::Shape,
-    util::{Color, Ray, vec::*},
+    bsdf::{Bsdf, Lambertian, face_forward},
+    shapes::{Shape, Hit},
+    util::{Aabb, Color, Ray, Wavelengths, random, vec::*},
     renderer::Vertex
 };
 
+/// How much a diffuse or glossy bounce widens the cone of a ray, in radians
+const ROUGH_SPREAD_ANGLE: f32 = 0.1;
+/// See-through surfaces a ray can pass before it is considered to have escaped
+const MAX_PASS_THROUGHS: usize = 64;
+
 pub type RenderSpace = u8;
 pub type RenderFormat = Rgba<RenderSpace>;
 
@@ -21,7 +30,17 @@ pub type RenderFormat = Rgba<RenderSpace>;
 pub struct Scene {
     pub camera: Camera,
     pub shapes: Vec<Box<dyn Shape>>,
-    pub lights: Vec<Box<dyn Light>>
+    pub lights: Vec<Box<dyn Light>>,
+    pub splats: Vec<GaussianSplats>,
+    /// Fog, smoke and other media light travels through between surfaces
+    pub media: Vec<Medium>,
+    /// Traces every path at a few random wavelengths instead of in RGB, see [Scene::set_spectral]
+    pub spectral: bool,
+    bvh: Bvh,
+    /// Indices of the emissive shapes that can be sampled for direct lighting
+    emitters: Vec<usize>,
+    /// Whether each shape is in `emitters`
+    is_emitter: Vec<bool>
 }
 
 impl Default for Scene {
@@ -29,7 +48,13 @@ impl Default for Scene {
         Self {
             lights: Vec::new(),
             shapes: Vec::new(),
-            camera: Camera::new()
+            splats: Vec::new(),
+            media: Vec::new(),
+            spectral: false,
+            camera: Camera::new(),
+            bvh: Bvh::default(),
+            emitters: Vec::new(),
+            is_emitter: Vec::new()
         }
     }
 }
@@ -73,6 +98,25 @@ impl Scene {
         self
     }
 
+    /// Adds a set of [GaussianSplats] to the scene, composited in front of any shapes behind them
+    pub fn add_splats(&mut self, splats: GaussianSplats) -> &mut Self {
+        self.splats.push(splats);
+        self
+    }
+
+    /// Adds a participating [Medium] to the scene, filling all of space unless it has a boundary
+    pub fn add_medium(&mut self, medium: Medium) -> &mut Self {
+        self.media.push(medium);
+        self
+    }
+
+    /// Renders spectrally, so that dispersive materials split light into its colors.
+    /// Colors are turned into spectra along the way and back into RGB for every pixel
+    pub fn set_spectral(&mut self, spectral: bool) -> &mut Self {
+        self.spectral = spectral;
+        self
+    }
+
     /// Adds a camera to the array of cameras
     /// when [`Scene::render()`] is called, resulting images
     /// are made from all of the given cameras
@@ -81,6 +125,46 @@ impl Scene {
         self
     }
 
+    /// Rebuilds the acceleration structure used to find which shapes rays hit
+    pub fn build_bvh(&mut self) {
+        self.bvh = Bvh::new(&self.get_shape_bounds());
+    }
+
+    /// Brings the acceleration structure up to date with the shapes, called before every render.
+    /// Moved shapes are refitted, the structure is only rebuilt when shapes were added, removed,
+    /// gained or lost their bounds, or refitting degraded it too much
+    pub fn update_bvh(&mut self) {
+        let bounds = self.get_shape_bounds();
+
+        if !self.bvh.can_refit(&bounds) {
+            self.bvh = Bvh::new(&bounds);
+            return;
+        }
+
+        self.bvh.refit(&bounds);
+
+        if self.bvh.needs_rebuild() {
+            self.bvh = Bvh::new(&bounds);
+        }
+    }
+
+    /// Finds the glowing shapes that direct lighting can aim rays at, called before every render
+    pub fn update_emitters(&mut self) {
+        self.is_emitter = self.shapes
+            .iter()
+            .map(|shape| shape.get_material().is_emissive() && shape.sample_surface().is_some())
+            .collect();
+
+        self.emitters = (0..self.shapes.len()).filter(|&index| self.is_emitter[index]).collect();
+    }
+
+    fn get_shape_bounds(&self) -> Vec<Aabb> {
+        self.shapes
+            .iter()
+            .map(|shape| shape.get_bounds())
+            .collect()
+    }
+
     /// Output all of the views from all of the `.cameras` as .png images
     pub fn render(&mut self, dimensions: (u32, u32)) -> Vec<Vertex> {        
         self.render_camera(dimensions)
@@ -88,12 +172,17 @@ impl Scene {
 
     /// Render the view from a camera at the given index
     pub fn render_camera(&mut self, dimensions: (u32, u32)) -> Vec<Vertex>{
+        self.update_bvh();
+        self.update_emitters();
+
         let (width, height) = dimensions;
         let aspect_ratio = width as f32 / height as f32;
         let mut vertices: Vec<Vertex> = Vec::with_capacity((width * height) as usize);
 
-        let camera_position = self.camera.position.clone();
-        let ray_directions = self.camera.get_ray_directions(width, height, aspect_ratio);
+        let camera_position = self.camera.position;
+        let spread_angle = self.camera.get_spread_angle(height);
+        self.camera.get_ray_directions(width, height, aspect_ratio);
+        let ray_directions = &self.camera.rays;
 
         for y in 0..height {
             //print!("Rendering image... Rows left: {}", height - y);
@@ -105,13 +194,14 @@ impl Scene {
                 let mut ray = Ray::new(
                     camera_position,
                     ray_directions[index]
-                );
+                ).set_cone(0.0, spread_angle);
 
-                let color = evaluate_pixel(
-                    &mut ray,
-                    &self.shapes,
-                    &self.lights
-                );
+                let color = if self.spectral {
+                    let wavelengths = Wavelengths::sample();
+                    wavelengths.to_rgb(&evaluate_pixel(&mut ray, self, Some(&wavelengths)))
+                } else {
+                    evaluate_pixel(&mut ray, self, None)
+                };
 
                 let x = ((x as f32 / width as f32) * 2.0) - 1.0;
                 let y = ((y as f32 / height as f32) * 2.0) - 1.0;
@@ -122,123 +212,343 @@ impl Scene {
                 // }); 
 
                 vertices.push(Vertex {
-                    position: [x as f32, y as f32, 0.0],
+                    position: [x, y, 0.0],
                     color: [color.r, color.g, color.b]
                 });       
             }
         }
 
-        return vertices;
+        vertices
     }
 }
 
-fn evaluate_pixel(
-    ray: &mut Ray,
-    shapes: &[Box<dyn Shape>],
-    lights: &[Box<dyn Light>]
-) -> Color {
-    let sky_color = Color::rgb(0.005, 0.005, 0.005);
+/// Light arriving along the ray, in RGB, or at the given wavelengths when rendering spectrally
+fn evaluate_pixel(ray: &mut Ray, scene: &Scene, wavelengths: Option<&Wavelengths>) -> Color {
+    let Scene { bvh, shapes, lights, splats, media, .. } = scene;
+    // Colors are turned into spectra right before they are used
+    let convert = |color: Color| wavelengths.map_or(color, |wavelengths| wavelengths.from_rgb(&color));
+
+    let sky_color = convert(Color::rgb(0.005, 0.005, 0.005));
 
     let mut color = Color::rgb(0.0, 0.0, 0.0);
-    let mut multiplier = 1.0;
+    // How much of the light arriving along the ray reaches the camera
+    let mut throughput = Color::rgb(1.0, 1.0, 1.0);
+    // Whether only the hero wavelength is left on the path
+    let mut terminated = false;
+    // Emitters hit after a diffuse or glossy bounce were already counted by sampling them directly
+    let mut is_specular = true;
 
     for _ in 0..10 {
-        let shape_hit = shoot_ray(&ray, &shapes);
+        let shape_hit = shoot_ray(ray, bvh, shapes);
         //println!("{:?}", shape_hit);
-        if shape_hit.is_none() {
-            color.add_mut(&sky_color.mul_by(multiplier));
-            break;
+
+        // Media in front of whatever the ray hits may scatter the light before it gets there
+        let surface_distance = shape_hit.as_ref().map_or(f32::INFINITY, |(_, hit, _)| hit.distance);
+        let scatter = sample_media(ray, surface_distance * ray.direction.magnitude(), media, wavelengths, &mut throughput);
+
+        // Gaussians in front of wherever the light comes from cover part of it
+        let max_distance = scatter.map_or(surface_distance, |(_, _, distance)| distance / ray.direction.magnitude());
+        if !splats.is_empty() {
+            let (splat_color, transmittance) = trace_all(splats, ray, max_distance);
+            color.add_mut(&convert(splat_color).mul(&throughput));
+            throughput.mul_by_mut(transmittance);
+        }
+
+        if let Some((position, index, distance)) = scatter {
+            let phase = &media[index].phase;
+            let direction = ray.direction.normalize();
+
+            let medium_color = get_medium_color(phase, &direction, &position, lights)
+                .add(&sample_emitter(
+                    |wi| { let value = phase.evaluate(&direction, wi); Color::rgb(value, value, value) },
+                    &position, None, scene, wavelengths
+                ));
+            color.add_mut(&medium_color.mul(&throughput));
+
+            // Sampling the phase function exactly leaves the throughput unchanged
+            ray.cone_width = ray.get_cone_width(distance / ray.direction.magnitude());
+            ray.position = position;
+            ray.direction = phase.sample(&direction);
+            ray.spread_angle += ROUGH_SPREAD_ANGLE;
+            is_specular = false;
+            continue;
         }
-        
+
+        let Some((shape_index, mut hit, is_front_face)) = shape_hit else {
+            color.add_mut(&sky_color.mul(&throughput));
+            break;
+        };
+        hit.update_footprint(ray);
+
         // At this point, we have the closest object the ray hit
-        let (shape_index, hit_distance) = shape_hit.unwrap();
         let shape = &shapes[shape_index];
-        let material = shape.get_material();
+        // Textured properties are looked up where the ray hit
+        let material = shape.get_hit_material(&hit).evaluate(&hit);
+        let material = match wavelengths {
+            Some(wavelengths) => material.to_spectral(wavelengths),
+            None => material
+        };
+
+        // Hitting a surface from behind means the ray travelled through its inside
+        if !is_front_face {
+            let distance = hit.distance * ray.direction.magnitude();
+            throughput.mul_mut(&material.get_transmittance(distance));
+        }
 
-        let (hit_color, hit_position, hit_normal) = get_hit_color(
-            &ray, 
-            &shapes[shape_index], 
-            hit_distance, 
-            lights
-        );
+        // Light given off by the surface itself, only from its front
+        if material.is_emissive() && is_front_face
+            && (is_specular || !scene.is_emitter[shape_index])
+        {
+            color.add_mut(&material.get_emission().mul(&throughput));
+        }
 
-        // Add the color of this ray bounce
-        // to the total color to be displayed by the pixel
-        color.add_mut(&hit_color.mul_by(multiplier));
-        multiplier *= 0.5;
+        // Shade with the normal perturbed by normal and bump maps,
+        // but keep leaving from the side of the real surface
+        let mut geometric_normal = hit.normal;
+        hit.normal = material.get_shading_normal(&hit);
+
+        let mut wo = ray.direction.normalize().invert();
+        ray.cone_width = ray.get_cone_width(hit.distance);
+
+        // Light entering subsurface materials wanders around inside and leaves somewhere else,
+        // from where it is shaded as if by a white diffuse surface
+        let bsdf = match &material.subsurface {
+            Some(subsurface) if is_front_face => {
+                // The walk starts on the real surface, bumps would let it start outside the shape
+                let entry = Hit { normal: geometric_normal, ..hit.clone() };
+                let Some((exit, weight)) = random_walk(shape.as_ref(), &entry, subsurface) else { break };
+                throughput.mul_mut(&weight);
+
+                // Light leaving through a cutout carries on without being shaded
+                let exit = match exit {
+                    Exit::Surface(exit) => exit,
+                    Exit::PassThrough(exit) => {
+                        ray.position = exit.position;
+                        ray.direction = exit.direction;
+                        is_specular = true;
+                        continue;
+                    }
+                };
+
+                geometric_normal = exit.normal;
+                wo = exit.normal;
+                hit = exit;
+
+                Box::new(Lambertian::new(Color::rgb(1.0, 1.0, 1.0)))
+            },
+            _ if wavelengths.is_some() => material.get_spectral_bsdf(),
+            _ => material.get_bsdf()
+        };
+
+        // Add the light reaching the camera directly from this bounce
+        let hit_color = get_hit_color(bsdf.as_ref(), &wo, &hit, lights)
+            .add(&sample_emitter(
+                |wi| bsdf.eval(&wo, wi, &hit.normal).mul_by(wi.dot(&hit.normal).abs()),
+                &hit.position, Some(&hit.normal), scene, wavelengths
+            ));
+        color.add_mut(&hit_color.mul(&throughput));
+
+        // Continue in the direction the surface scatters light from
+        let Some(sample) = bsdf.sample(&wo, &hit.normal) else { break };
+        throughput.mul_mut(&sample.weight);
+        if sample.is_hero_only {
+            terminate_secondary_wavelengths(&mut throughput, &mut terminated);
+        }
+        is_specular = sample.is_specular;
 
-        // Move the ray backwards a tiny bit
+        // Move the ray off the surface on the side it leaves from
         // So that we dont collide with ourselves
-        ray.position = hit_normal.mul_by(0.0001).add(&hit_position);
+        let offset = face_forward(&geometric_normal, &sample.direction).mul_by(0.0001);
+        ray.position = hit.position.add(&offset);
+        ray.direction = sample.direction;
 
-        // Shift the direction a little bit based on the roughness of the material
-        // and some randomness
-        let roughness = material.roughness * Vec3::random_value(-0.5, 0.5);
-
-        ray.direction = ray.direction.reflect(&hit_normal.add_by(roughness));
+        // Rough bounces scatter the cone, blurring what is seen through them
+        if !sample.is_specular {
+            ray.spread_angle += ROUGH_SPREAD_ANGLE;
+        }
     }
 
     color
 }
 
-fn shoot_ray(ray: &Ray, shapes: &[Box<dyn Shape>]) -> Option<(usize, f32)> {
-    if shapes.is_empty() { return None; }
-
-    let mut closest_hit_distance = f32::MAX;
-    let mut closest_shape_index = 0;
+/// Drops every wavelength but the hero from the path, scaling the hero up by the three it now stands in for.
+/// Only the first time counts, after that the hero is all that's left
+fn terminate_secondary_wavelengths(throughput: &mut Color, terminated: &mut bool) {
+    if *terminated { return; }
 
-    for (i, shape) in shapes.iter().enumerate() {
-        if !shape.is_3d() || !shape.has_radius() { continue; }
-
-        let shape_position = shape.get_position();
-        let shape_radius = shape.get_radius().unwrap();
+    *throughput = Color::rgb(throughput.r * 3.0, 0.0, 0.0);
+    *terminated = true;
+}
 
-        let origin = ray.position.sub(&shape_position);
+/// Finds the closest surface along the ray, skipping over the see-through parts of cutout materials.
+/// Shadow rays go through here too, so cutouts also let light through.
+/// Also returns whether the ray hit the outside of the surface, coming from outside the shape
+fn shoot_ray(ray: &Ray, bvh: &Bvh, shapes: &[Box<dyn Shape>]) -> Option<(usize, Hit, bool)> {
+    let mut ray = *ray;
+    let mut travelled = 0.0;
 
-        let a = ray.direction.dot(&ray.direction);
-        let b = 2.0 * origin.dot(&ray.direction);
-        let c = origin.dot(&origin) - shape_radius.powi(2);
+    for _ in 0..MAX_PASS_THROUGHS {
+        let (index, mut hit) = bvh.intersect(&ray, |i| shapes[i].intersect(&ray))?;
 
-        // Determine the amount of intersactions between the ray
-        // and the radius of the circle
-        // < 0 means there is no collision
-        // = 0 means there is one collision
-        // > 0 means there are two collisions
-        // Quadratic formula discriminant:
-        // b^2 -4ac
-        let discriminant = b * b - 4.0 * a * c;
+        let passes_through = shapes[index].get_hit_material(&hit).passes_through(&hit);
 
-        if discriminant >= 0.0 {
-            let closest_hit = (-b - discriminant.sqrt()) / (2.0 * a);
-            if closest_hit > 0.0 && closest_hit < closest_hit_distance {
-                closest_hit_distance = closest_hit;
-                closest_shape_index = i;
-            }
+        if !passes_through {
+            let is_front_face = hit.is_front_face(&ray);
+            hit.distance += travelled;
+            return Some((index, hit, is_front_face));
         }
-    }
 
-    // This means there was no hit, stop here
-    if closest_hit_distance == f32::MAX { return None; }
+        // Continue from just past the surface
+        let step = hit.distance + 0.0001 / ray.direction.magnitude();
+        ray.position = ray.get_point(step);
+        travelled += step;
+    }
 
-    Some((closest_shape_index, closest_hit_distance))
+    None
 }
 
 fn get_hit_color(
-    ray: &Ray,
-    shape: &Box<dyn Shape>,
-    hit_distance: f32,
+    bsdf: &dyn Bsdf,
+    wo: &Vec3,
+    hit: &Hit,
     lights: &[Box<dyn Light>]
-) -> (Color, Vec3, Vec3) {
-    let shape_position = shape.get_position();
-    let shape_color = shape.get_surface_color();
+) -> Color {
+    let normal = face_forward(&hit.normal, wo);
+    let mut color = Color::rgb(0.0, 0.0, 0.0);
 
-    let origin = ray.position.sub(&shape_position);
-    let hit_position = ray.direction
-        .mul_by(hit_distance)
-        .add(&origin);
+    for light in lights {
+        let direction = light.get_direction(&hit.position);
+        let light_intensity = light.get_intensity(&normal);
 
-    let normal = hit_position.normalize();
-    let light_intensity = lights[0].get_intensity(&normal);
+        color.add_mut(&bsdf.eval(wo, &direction, &hit.normal).mul_by(light_intensity));
+    }
+
+    color
+}
+
+/// Light from the scene's lights scattered towards the camera by a medium.
+/// Like on surfaces, these lights cast no shadows and are not dimmed by media
+fn get_medium_color(
+    phase: &HenyeyGreenstein,
+    direction: &Vec3,
+    position: &Vec3,
+    lights: &[Box<dyn Light>]
+) -> Color {
+    let mut color = Color::rgb(0.0, 0.0, 0.0);
 
-    (shape_color.mul_by(light_intensity), hit_position, normal)
+    for light in lights {
+        let to_light = light.get_direction(position);
+        let value = phase.evaluate(direction, &to_light) * light.get_intensity(&to_light);
+
+        color.add_mut(&Color::rgb(value, value, value));
+    }
+
+    color
+}
+
+/// Light arriving directly from a random point on a random emissive shape, if nothing blocks it.
+/// `scatter` gives how much of the light arriving from a direction is scattered towards the camera,
+/// and `normal` is the surface to leave from, `None` inside media
+fn sample_emitter(
+    scatter: impl Fn(&Vec3) -> Color,
+    position: &Vec3,
+    normal: Option<&Vec3>,
+    scene: &Scene,
+    wavelengths: Option<&Wavelengths>
+) -> Color {
+    let Scene { bvh, shapes, emitters, media, .. } = scene;
+    let black = Color::rgb(0.0, 0.0, 0.0);
+    if emitters.is_empty() { return black; }
+
+    let index = emitters[((random() * emitters.len() as f32) as usize).min(emitters.len() - 1)];
+    let emitter = &shapes[index];
+    let Some(sample) = emitter.sample_surface() else { return black };
+
+    let to_light = sample.position.sub(position);
+    let distance = to_light.magnitude();
+    if distance <= 0.0 { return black; }
+    let direction = to_light.div_by(distance);
+
+    // Emitters only glow from their front
+    let cos_light = -direction.dot(&sample.normal);
+    if cos_light <= 0.0 { return black; }
+
+    let scattered = scatter(&direction);
+    if scattered.r <= 0.0 && scattered.g <= 0.0 && scattered.b <= 0.0 { return black; }
+
+    let offset = normal.map_or(Vec3::new(0.0, 0.0, 0.0), |normal| face_forward(normal, &direction).mul_by(0.0001));
+    let shadow_ray = Ray::new(position.add(&offset), direction);
+    let is_blocked = shoot_ray(&shadow_ray, bvh, shapes)
+        .is_some_and(|(_, blocker, _)| blocker.distance < distance * (1.0 - 1e-3));
+    if is_blocked { return black; }
+
+    // Converts the density per unit of area to one per solid angle
+    let pdf = sample.pdf * distance * distance / cos_light / emitters.len() as f32;
+
+    let material = emitter.get_material();
+    let material = match wavelengths {
+        Some(wavelengths) => material.to_spectral(wavelengths),
+        None => material
+    };
+
+    material
+        .get_emission()
+        .mul(&scattered)
+        .mul(&get_transmittance(&shadow_ray, distance, media, wavelengths))
+        .div_by(pdf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bsdf::Dielectric, shapes::{Metaballs, Sphere}, util::Material};
+
+    #[test]
+    fn shapes_gaining_bounds_get_hit() {
+        let mut scene = Scene::new();
+        scene.add_shape(Metaballs::new(1.0, Material::default()));
+        scene.update_bvh();
+
+        // Swapping the empty shape for one that has bounds keeps the amount of shapes the same
+        scene.shapes[0] = Box::new(Sphere::new(Vec3::new(0.0, 0.0, 5.0), 1.0, Material::default()));
+        scene.update_bvh();
+
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(shoot_ray(&ray, &scene.bvh, &scene.shapes).is_some());
+    }
+
+    #[test]
+    fn reports_which_side_was_hit() {
+        let mut scene = Scene::new();
+        scene.add_shape(Sphere::new(Vec3::new(0.0, 0.0, 5.0), 1.0, Material::default()));
+        scene.update_bvh();
+
+        let outside = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(shoot_ray(&outside, &scene.bvh, &scene.shapes).unwrap().2);
+
+        let inside = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(!shoot_ray(&inside, &scene.bvh, &scene.shapes).unwrap().2);
+    }
+
+    #[test]
+    fn hero_only_paths_scale_once() {
+        let bsdf = Dielectric::new(1.5, Color::rgb(1.0, 1.0, 1.0)).set_hero_only(true);
+        let (wo, normal) = (Vec3::new(0.0, 1.0, 1.0).normalize(), Vec3::new(0.0, 1.0, 0.0));
+
+        // Going through two dispersive surfaces only makes up for the dropped wavelengths once
+        let mut throughput = Color::rgb(1.0, 1.0, 1.0);
+        let mut terminated = false;
+        let mut expected = 3.0;
+        for _ in 0..2 {
+            let sample = bsdf.sample(&wo, &normal).unwrap();
+            assert!(sample.is_hero_only);
+
+            throughput.mul_mut(&sample.weight);
+            terminate_secondary_wavelengths(&mut throughput, &mut terminated);
+            expected *= sample.weight.r;
+        }
+
+        assert!((throughput.r - expected).abs() < 1e-5);
+        assert!(throughput.g == 0.0 && throughput.b == 0.0);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,433 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::Path
+};
+
+use super::bvh::Bvh;
+use crate::util::{
+    Aabb,
+    Color,
+    Quat,
+    Ray,
+    matrix::Matrix3,
+    vec::*
+};
+
+/// Gaussians are cut off this many standard deviations from their center
+const CUTOFF_SIGMA: f32 = 3.0;
+/// Contributions weaker than this are skipped
+const MIN_ALPHA: f32 = 1.0 / 255.0;
+/// Compositing stops once less than this much light makes it through
+const MIN_TRANSMITTANCE: f32 = 1e-3;
+/// Gaussians per leaf of the acceleration structure
+const LEAF_SIZE: usize = 8;
+
+/// Normalization constants of the real spherical harmonics, per band
+const SH_C0: f32 = 0.282_094_8;
+const SH_C1: f32 = 0.488_602_5;
+const SH_C2: [f32; 5] = [1.092_548_5, -1.092_548_5, 0.315_391_57, -1.092_548_5, 0.546_274_24];
+const SH_C3: [f32; 7] = [-0.590_043_6, 2.890_611_4, -0.457_045_8, 0.373_176_34, -0.457_045_8, 1.445_305_7, -0.590_043_6];
+
+/// A single anisotropic 3D gaussian
+#[derive(Debug, Clone, Copy)]
+pub struct Gaussian {
+    pub position: Vec3,
+    /// Standard deviation along each of the gaussian's own axes
+    pub scale: Vec3,
+    pub rotation: Quat,
+    /// Alpha at the center of the gaussian, 0.0 - 1.0
+    pub opacity: f32
+}
+
+impl Gaussian {
+    /// Inverse of the covariance `R S S^T R^T`
+    fn get_inverse_covariance(&self) -> Matrix3 {
+        let rotation = self.rotation.to_matrix3();
+        let inverse_scale = Vec3::new(
+            1.0 / (self.scale.x * self.scale.x),
+            1.0 / (self.scale.y * self.scale.y),
+            1.0 / (self.scale.z * self.scale.z)
+        );
+
+        rotation
+            .mul(&Matrix3::scaling(inverse_scale))
+            .mul(&rotation.transpose())
+    }
+
+    /// Box around the gaussian's ellipsoid at the cutoff distance
+    fn get_bounds(&self) -> Aabb {
+        let rotation = self.rotation.to_matrix3().data;
+        let axes = self.scale.mul_by(CUTOFF_SIGMA);
+
+        let half_extent = |row: [f32; 3]| {
+            ((row[0] * axes.x).powi(2) + (row[1] * axes.y).powi(2) + (row[2] * axes.z).powi(2)).sqrt()
+        };
+
+        let half_extent = Vec3::new(half_extent(rotation[0]), half_extent(rotation[1]), half_extent(rotation[2]));
+        Aabb::new(self.position.sub(&half_extent), self.position.add(&half_extent))
+    }
+}
+
+/// Scene made of 3D gaussians with view dependent colors, as produced by gaussian splatting.
+/// Rays composite the gaussians they pass through front to back before reaching any surface
+pub struct GaussianSplats {
+    pub gaussians: Vec<Gaussian>,
+    /// Spherical harmonic coefficients for the RGB channels, `(degree + 1)²` per gaussian
+    pub coefficients: Vec<Vec3>,
+    sh_degree: usize,
+    inverse_covariances: Vec<Matrix3>,
+    bvh: Bvh
+}
+
+/// Gaussian a ray passes through, `distance` being where its contribution peaks
+struct SplatHit {
+    index: usize,
+    distance: f32,
+    alpha: f32
+}
+
+impl GaussianSplats {
+    /// `coefficients` holds `(sh_degree + 1)²` spherical harmonic coefficients per gaussian,
+    /// `sh_degree` can be at most 3
+    pub fn new(gaussians: Vec<Gaussian>, coefficients: Vec<Vec3>, sh_degree: usize) -> Self {
+        assert!(sh_degree <= 3, "Spherical harmonics are supported up to degree 3");
+        assert_eq!(coefficients.len(), gaussians.len() * (sh_degree + 1).pow(2), "Wrong amount of coefficients");
+
+        let bounds: Vec<Aabb> = gaussians.iter().map(Gaussian::get_bounds).collect();
+
+        Self {
+            inverse_covariances: gaussians.iter().map(Gaussian::get_inverse_covariance).collect(),
+            bvh: Bvh::with_leaf_size(&bounds, LEAF_SIZE),
+            gaussians,
+            coefficients,
+            sh_degree
+        }
+    }
+
+    /// Loads the binary `.ply` files written by 3D gaussian splatting training
+    pub fn from_ply<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read_ply(BufReader::new(File::open(path)?))
+    }
+
+    /// Like [GaussianSplats::from_ply], reading the file's contents from `reader`
+    pub fn read_ply<R: BufRead>(mut reader: R) -> io::Result<Self> {
+        let (count, properties) = read_ply_header(&mut reader)?;
+
+        let columns: HashMap<&str, usize> = properties
+            .iter()
+            .enumerate()
+            .map(|(i, (name, _))| (name.as_str(), i))
+            .collect();
+
+        let column = |name: &str| {
+            columns.get(name).copied().ok_or_else(|| invalid_data(format!("Missing vertex property `{name}`")))
+        };
+
+        let position = [column("x")?, column("y")?, column("z")?];
+        let scale = [column("scale_0")?, column("scale_1")?, column("scale_2")?];
+        let rotation = [column("rot_0")?, column("rot_1")?, column("rot_2")?, column("rot_3")?];
+        let opacity = column("opacity")?;
+        let dc = [column("f_dc_0")?, column("f_dc_1")?, column("f_dc_2")?];
+
+        // Higher bands are stored all red, then all green, then all blue
+        let rest_count = properties.iter().filter(|(name, _)| name.starts_with("f_rest_")).count();
+        let sh_degree: usize = match rest_count {
+            0 => 0,
+            9 => 1,
+            24 => 2,
+            45 => 3,
+            _ => return Err(invalid_data(format!("Unexpected amount of SH coefficients: {rest_count}")))
+        };
+        let rest: Vec<usize> = (0..rest_count).map(|i| column(&format!("f_rest_{i}"))).collect::<io::Result<_>>()?;
+        let per_channel = rest_count / 3;
+
+        let mut gaussians = Vec::with_capacity(count);
+        let mut coefficients = Vec::with_capacity(count * (sh_degree + 1).pow(2));
+        let mut values = vec![0.0f32; properties.len()];
+
+        for _ in 0..count {
+            for (value, (_, kind)) in values.iter_mut().zip(&properties) {
+                *value = read_ply_value(&mut reader, kind)?;
+            }
+
+            // Scales are stored as logarithms and opacities before a sigmoid
+            gaussians.push(Gaussian {
+                position: Vec3::new(values[position[0]], values[position[1]], values[position[2]]),
+                scale: Vec3::new(values[scale[0]].exp(), values[scale[1]].exp(), values[scale[2]].exp()),
+                rotation: Quat::new(values[rotation[1]], values[rotation[2]], values[rotation[3]], values[rotation[0]]).normalize(),
+                opacity: 1.0 / (1.0 + (-values[opacity]).exp())
+            });
+
+            coefficients.push(Vec3::new(values[dc[0]], values[dc[1]], values[dc[2]]));
+            for i in 0..per_channel {
+                coefficients.push(Vec3::new(
+                    values[rest[i]],
+                    values[rest[i + per_channel]],
+                    values[rest[i + per_channel * 2]]
+                ));
+            }
+        }
+
+        Ok(Self::new(gaussians, coefficients, sh_degree))
+    }
+
+    /// Color of the gaussian seen from `direction`
+    pub fn get_color(&self, index: usize, direction: &Vec3) -> Color {
+        let count = (self.sh_degree + 1).pow(2);
+        let sh = &self.coefficients[index * count..(index + 1) * count];
+        let Vec3 { x, y, z } = *direction;
+
+        let mut color = sh[0].mul_by(SH_C0);
+
+        if self.sh_degree >= 1 {
+            color.add_mut(&sh[1].mul_by(-SH_C1 * y));
+            color.add_mut(&sh[2].mul_by(SH_C1 * z));
+            color.add_mut(&sh[3].mul_by(-SH_C1 * x));
+        }
+
+        if self.sh_degree >= 2 {
+            let (xx, yy, zz) = (x * x, y * y, z * z);
+            let band = [x * y, y * z, 2.0 * zz - xx - yy, x * z, xx - yy];
+
+            for (i, value) in band.iter().enumerate() {
+                color.add_mut(&sh[4 + i].mul_by(SH_C2[i] * value));
+            }
+        }
+
+        if self.sh_degree >= 3 {
+            let (xx, yy, zz) = (x * x, y * y, z * z);
+            let band = [
+                y * (3.0 * xx - yy),
+                x * y * z,
+                y * (4.0 * zz - xx - yy),
+                z * (2.0 * zz - 3.0 * xx - 3.0 * yy),
+                x * (4.0 * zz - xx - yy),
+                z * (xx - yy),
+                x * (xx - 3.0 * yy)
+            ];
+
+            for (i, value) in band.iter().enumerate() {
+                color.add_mut(&sh[9 + i].mul_by(SH_C3[i] * value));
+            }
+        }
+
+        // Colors are stored shifted down by 0.5
+        Color::rgb((color.x + 0.5).max(0.0), (color.y + 0.5).max(0.0), (color.z + 0.5).max(0.0))
+    }
+
+    /// Where along the ray the gaussian peaks and how opaque it is there
+    fn evaluate(&self, ray: &Ray, index: usize, max_distance: f32) -> Option<SplatHit> {
+        let gaussian = &self.gaussians[index];
+        let inverse_covariance = &self.inverse_covariances[index];
+
+        let offset = gaussian.position.sub(&ray.position);
+        let projected_direction = inverse_covariance.mul_vec3(&ray.direction);
+        let denominator = ray.direction.dot(&projected_direction);
+        if denominator <= 0.0 { return None; }
+
+        let distance = offset.dot(&projected_direction) / denominator;
+        if distance <= 0.0 || distance >= max_distance { return None; }
+
+        // Squared distance in standard deviations from the center at the peak
+        let closest = ray.get_point(distance).sub(&gaussian.position);
+        let squared = closest.dot(&inverse_covariance.mul_vec3(&closest));
+        if squared > CUTOFF_SIGMA * CUTOFF_SIGMA { return None; }
+
+        let alpha = (gaussian.opacity * (-0.5 * squared).exp()).min(0.99);
+        if alpha < MIN_ALPHA { return None; }
+
+        Some(SplatHit { index, distance, alpha })
+    }
+
+    /// Composites the gaussians along the ray up to `max_distance` front to back.
+    /// Returns the accumulated color and how much of what lies behind still shows through
+    pub fn trace(&self, ray: &Ray, max_distance: f32) -> (Color, f32) {
+        trace_all(std::slice::from_ref(self), ray, max_distance)
+    }
+}
+
+/// Composites the gaussians of every set along the ray up to `max_distance` front to back.
+/// They're sorted together, so sets that overlap blend the same no matter their order
+pub fn trace_all(sets: &[GaussianSplats], ray: &Ray, max_distance: f32) -> (Color, f32) {
+    let mut hits: Vec<(&GaussianSplats, SplatHit)> = Vec::new();
+    for set in sets {
+        set.bvh.intersect_all(ray, max_distance, |index| {
+            if let Some(hit) = set.evaluate(ray, index, max_distance) {
+                hits.push((set, hit));
+            }
+        });
+    }
+
+    hits.sort_by(|(_, a), (_, b)| a.distance.total_cmp(&b.distance));
+
+    let direction = ray.direction.normalize();
+    let mut color = Color::rgb(0.0, 0.0, 0.0);
+    let mut transmittance = 1.0;
+
+    for (set, hit) in hits {
+        let splat_color = set.get_color(hit.index, &direction);
+        color.add_mut(&splat_color.mul_by(hit.alpha * transmittance));
+        transmittance *= 1.0 - hit.alpha;
+
+        if transmittance < MIN_TRANSMITTANCE { break; }
+    }
+
+    // The alpha channel isn't used when compositing
+    color.a = 1.0;
+    (color, transmittance)
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads the header, returning the amount of vertices and their properties
+fn read_ply_header<R: BufRead>(reader: &mut R) -> io::Result<(usize, Vec<(String, String)>)> {
+    let mut line = String::new();
+    let mut count = None;
+    let mut properties = Vec::new();
+    let mut in_vertex = false;
+
+    reader.read_line(&mut line)?;
+    if line.trim() != "ply" { return Err(invalid_data("Not a PLY file".into())); }
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid_data("PLY header never ends".into()));
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["end_header"] => break,
+            ["format", format, ..] if *format != "binary_little_endian" => {
+                return Err(invalid_data(format!("Unsupported PLY format `{format}`")));
+            },
+            ["element", name, amount] => {
+                // Only the vertex element is read, so it has to come first
+                in_vertex = *name == "vertex";
+                if in_vertex {
+                    count = Some(amount.parse().map_err(|_| invalid_data("Invalid vertex count".into()))?);
+                } else if count.is_none() {
+                    return Err(invalid_data("The vertex element has to come first".into()));
+                }
+            },
+            ["property", "list", ..] if in_vertex => {
+                return Err(invalid_data("List properties are not supported on vertices".into()));
+            },
+            ["property", kind, name] if in_vertex => properties.push((name.to_string(), kind.to_string())),
+            _ => {}
+        }
+    }
+
+    let count = count.ok_or_else(|| invalid_data("No vertex element".into()))?;
+    Ok((count, properties))
+}
+
+fn read_ply_value<R: Read>(reader: &mut R, kind: &str) -> io::Result<f32> {
+    let mut bytes = [0u8; 8];
+
+    Ok(match kind {
+        "char" | "int8" => { reader.read_exact(&mut bytes[..1])?; bytes[0] as i8 as f32 },
+        "uchar" | "uint8" => { reader.read_exact(&mut bytes[..1])?; bytes[0] as f32 },
+        "short" | "int16" => { reader.read_exact(&mut bytes[..2])?; i16::from_le_bytes([bytes[0], bytes[1]]) as f32 },
+        "ushort" | "uint16" => { reader.read_exact(&mut bytes[..2])?; u16::from_le_bytes([bytes[0], bytes[1]]) as f32 },
+        "int" | "int32" => { reader.read_exact(&mut bytes[..4])?; i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32 },
+        "uint" | "uint32" => { reader.read_exact(&mut bytes[..4])?; u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32 },
+        "float" | "float32" => { reader.read_exact(&mut bytes[..4])?; f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) },
+        "double" | "float64" => { reader.read_exact(&mut bytes)?; f64::from_le_bytes(bytes) as f32 },
+        _ => return Err(invalid_data(format!("Unsupported PLY property type `{kind}`")))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gaussian(position: Vec3, opacity: f32) -> Gaussian {
+        Gaussian {
+            position,
+            scale: Vec3::new(0.5, 0.5, 0.5),
+            rotation: Quat::identity(),
+            opacity
+        }
+    }
+
+    #[test]
+    fn composites_front_to_back() {
+        // Half transparent red in front of half transparent green
+        let splats = GaussianSplats::new(
+            vec![gaussian(Vec3::new(0.0, 0.0, 5.0), 0.5), gaussian(Vec3::new(0.0, 0.0, 8.0), 0.5)],
+            vec![Vec3::new(0.5 / SH_C0, -0.5 / SH_C0, -0.5 / SH_C0), Vec3::new(-0.5 / SH_C0, 0.5 / SH_C0, -0.5 / SH_C0)],
+            0
+        );
+
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        let (color, transmittance) = splats.trace(&ray, f32::INFINITY);
+
+        assert!((color.r - 0.5).abs() < 1e-4);
+        assert!((color.g - 0.25).abs() < 1e-4);
+        assert!((transmittance - 0.25).abs() < 1e-4);
+
+        // A surface in between hides the second gaussian
+        let (color, transmittance) = splats.trace(&ray, 6.5);
+        assert!(color.g.abs() < 1e-4);
+        assert!((transmittance - 0.5).abs() < 1e-4);
+
+        // Missing everything leaves the ray untouched
+        let ray = Ray::new(Vec3::new(5.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(splats.trace(&ray, f32::INFINITY).1, 1.0);
+    }
+
+    #[test]
+    fn overlapping_sets_interleave() {
+        let red = Vec3::new(0.5 / SH_C0, -0.5 / SH_C0, -0.5 / SH_C0);
+        let green = Vec3::new(-0.5 / SH_C0, 0.5 / SH_C0, -0.5 / SH_C0);
+
+        // Red gaussians at 5 and 9 with a green one from another set between them
+        let reds = GaussianSplats::new(
+            vec![gaussian(Vec3::new(0.0, 0.0, 5.0), 0.5), gaussian(Vec3::new(0.0, 0.0, 9.0), 0.5)],
+            vec![red, red],
+            0
+        );
+        let greens = GaussianSplats::new(vec![gaussian(Vec3::new(0.0, 0.0, 7.0), 0.5)], vec![green], 0);
+        let mut sets = vec![reds, greens];
+
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        let forwards = trace_all(&sets, &ray, f32::INFINITY);
+        sets.reverse();
+        let backwards = trace_all(&sets, &ray, f32::INFINITY);
+
+        // Red, then green, then red, whichever set comes first
+        for (color, transmittance) in [forwards, backwards] {
+            assert!((color.r - 0.625).abs() < 1e-4);
+            assert!((color.g - 0.25).abs() < 1e-4);
+            assert!((transmittance - 0.125).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn loads_ply() {
+        let mut bytes = b"ply\nformat binary_little_endian 1.0\nelement vertex 1\n".to_vec();
+        let names = ["x", "y", "z", "f_dc_0", "f_dc_1", "f_dc_2", "opacity", "scale_0", "scale_1", "scale_2", "rot_0", "rot_1", "rot_2", "rot_3"];
+        for name in names {
+            bytes.extend_from_slice(format!("property float {name}\n").as_bytes());
+        }
+        bytes.extend_from_slice(b"end_header\n");
+        for value in [1.0f32, 2.0, 3.0, 0.1, 0.2, 0.3, 0.0, 0.0, 1.0f32.ln(), 2.0f32.ln(), 2.0, 0.0, 0.0, 0.0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let splats = GaussianSplats::read_ply(io::Cursor::new(bytes)).unwrap();
+
+        let loaded = &splats.gaussians[0];
+        assert_eq!(splats.gaussians.len(), 1);
+        assert_eq!(loaded.position.z, 3.0);
+        assert!((loaded.opacity - 0.5).abs() < 1e-6);
+        assert!((loaded.scale.z - 2.0).abs() < 1e-5);
+        assert!((loaded.rotation.w - 1.0).abs() < 1e-6);
+        assert_eq!(splats.coefficients[0].y, 0.2);
+    }
+}
\ No newline at end of file
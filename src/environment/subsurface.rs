@@ -0,0 +1,122 @@
+use std::f32::consts::PI;
+
+use crate::{
+    bsdf::{Bsdf, Lambertian},
+    shapes::{Shape, Hit},
+    util::{Color, Ray, Subsurface, random, vec::*}
+};
+
+/// Scattering events after which light is considered absorbed
+const MAX_STEPS: usize = 256;
+/// Distance rays are pushed off the surface so they don't hit it again right away
+const OFFSET: f32 = 0.0001;
+
+/// Where light leaves a shape after a [random_walk]
+#[derive(Clone)]
+pub enum Exit {
+    /// Crosses the surface at the hit, whose normal faces out
+    Surface(Hit),
+    /// Goes straight through a see-through part of the surface and carries on along the ray
+    PassThrough(Ray)
+}
+
+/// Follows light entering `shape` at `hit` as it scatters around inside until it leaves again.
+/// Returns where it leaves and how much of the light makes it there.
+/// `None` when the light is absorbed or escapes through a hole in the shape
+pub fn random_walk(shape: &dyn Shape, hit: &Hit, subsurface: &Subsurface) -> Option<(Exit, Color)> {
+    let (scattering, extinction) = subsurface.get_coefficients();
+    let extinction = [extinction.r, extinction.g, extinction.b];
+
+    // Light crosses the boundary diffusely, as if through a white Lambertian surface
+    let entry = Lambertian::new(Color::rgb(1.0, 1.0, 1.0)).sample(&hit.normal.invert(), &hit.normal)?;
+    let mut ray = Ray::new(hit.position.sub(&hit.normal.mul_by(OFFSET)), entry.direction);
+    let mut weight = Color::rgb(1.0, 1.0, 1.0);
+
+    for _ in 0..MAX_STEPS {
+        let boundary = shape.intersect(&ray)?;
+        let boundary_distance = boundary.distance * ray.direction.magnitude();
+
+        // Distances are picked for one channel, weighted by the average density over all of them
+        let channel = ((random() * 3.0) as usize).min(2);
+        let distance = -(1.0 - random()).ln() / extinction[channel];
+
+        if distance >= boundary_distance {
+            let transmittance = extinction.map(|sigma| (-sigma * boundary_distance).exp());
+            let pdf = (transmittance[0] + transmittance[1] + transmittance[2]) / 3.0;
+            weight.mul_mut(&Color::rgb(transmittance[0], transmittance[1], transmittance[2]).div_by(pdf));
+
+            // See-through parts of the surface let the light out without it crossing anything
+            if shape.get_hit_material(&boundary).passes_through(&boundary) {
+                let position = boundary.position.add(&ray.direction.normalize().mul_by(OFFSET));
+                return Some((Exit::PassThrough(Ray::new(position, ray.direction)), weight));
+            }
+
+            return Some((Exit::Surface(boundary), weight));
+        }
+
+        let transmittance = extinction.map(|sigma| (-sigma * distance).exp());
+        let pdf = (0..3).map(|i| extinction[i] * transmittance[i]).sum::<f32>() / 3.0;
+        weight.mul_mut(&scattering.mul(&Color::rgb(transmittance[0], transmittance[1], transmittance[2])).div_by(pdf));
+
+        // Scatter in a uniformly random direction
+        let z = 1.0 - 2.0 * random();
+        let ring = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * random();
+
+        ray = Ray::new(
+            ray.get_point(distance / ray.direction.magnitude()),
+            Vec3::new(ring * phi.cos(), ring * phi.sin(), z)
+        );
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{shapes::Sphere, util::Material};
+
+    #[test]
+    fn walks_out_of_closed_shapes() {
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, Material::default());
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = sphere.intersect(&ray).unwrap();
+
+        // White, thin media lose nothing, light always comes back out on the surface
+        let white = Subsurface::new(Color::rgb(1.0, 1.0, 1.0), Color::rgb(0.1, 0.1, 0.1));
+        let mut total = 0.0;
+        let walks = 500;
+
+        for _ in 0..walks {
+            if let Some((Exit::Surface(exit), weight)) = random_walk(&sphere, &hit, &white) {
+                assert!((exit.position.magnitude() - 1.0).abs() < 1e-3);
+                total += weight.r;
+            }
+        }
+
+        let average = total / walks as f32;
+        assert!(average > 0.8 && average < 1.1, "average {average}");
+
+        // Darker albedos absorb more
+        let dark = Subsurface::new(Color::rgb(0.2, 0.2, 0.2), Color::rgb(0.1, 0.1, 0.1));
+        let dark_total: f32 = (0..walks)
+            .filter_map(|_| random_walk(&sphere, &hit, &dark))
+            .map(|(_, weight)| weight.r)
+            .sum();
+        assert!(dark_total / (walks as f32) < average * 0.6);
+
+        // Light leaves shapes that are all cutout straight through their surface, just as bright
+        let sheer = Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, Material::default().set_opacity(0.0));
+        let mut sheer_total = 0.0;
+        for _ in 0..walks {
+            let Some((exit, weight)) = random_walk(&sheer, &hit, &white) else { continue };
+            let Exit::PassThrough(ray) = exit else { panic!("Cutouts don't stop the light") };
+
+            assert!((ray.position.magnitude() - 1.0).abs() < 1e-3);
+            assert!(ray.direction.dot(&ray.position) > 0.0);
+            sheer_total += weight.r;
+        }
+        assert!((sheer_total / walks as f32 - average).abs() < 0.15);
+    }
+}
\ No newline at end of file
@@ -1,4 +1,6 @@
 pub mod shapes;
 pub mod environment;
 pub mod util;
-pub mod renderer;
\ No newline at end of file
+pub mod renderer;
+pub mod bsdf;
+pub mod texture;
\ No newline at end of file
@@ -1,5 +1,5 @@
 use std::borrow::Cow;
-use wgpu::{hal::MAX_VERTEX_BUFFERS, util::DeviceExt};
+use wgpu::util::DeviceExt;
 use winit::{
     event::{Event, WindowEvent},
     event_loop::EventLoop,
@@ -16,8 +16,7 @@ use crate::{
     util::{
         Color,
         Material,
-        vec::*,
-        random_range
+        vec::*
     }
 };
 
@@ -72,7 +71,30 @@ impl Renderer {
     }
 
     pub fn setup_scene(&mut self) {
-        let mut spheres: Vec<Sphere> = Vec::new();
+        let spheres: Vec<Sphere> = vec![
+            // Top sphere
+            Sphere::new(
+                Vec3::new(-1.7, 0.0, 0.0),
+                1.0,
+                Material {
+                    albedo: Color::rgb(1.0, 0.0, 0.1),
+                    roughness: 0.1,
+                    metallic: 1.0,
+                    ..Default::default()
+                } 
+            ),
+            // Floor sphere
+            Sphere::new(
+                Vec3::new(0.1, 0.0, 0.0),
+                1.0,
+                Material {
+                    albedo: Color::rgb(0.0, 0.5, 0.0),
+                    roughness: 0.1,
+                    //metallic: 1.0,
+                    ..Default::default()
+                } 
+            )
+        ];
 
         // Generate random spheres
         // let sphere_count = 100;
@@ -94,34 +116,6 @@ impl Renderer {
         //     spheres.push(sphere);
         // }
 
-        // Top sphere
-        spheres.push(
-            Sphere::new(
-                Vec3::new(-1.7, 0.0, 0.0),
-                1.0,
-                Material {
-                    albedo: Color::rgb(1.0, 0.0, 0.1),
-                    roughness: 0.1,
-                    metallic: 1.0,
-                    ..Default::default()
-                } 
-            )
-        );
-
-        // Floor sphere
-        spheres.push(
-            Sphere::new(
-                Vec3::new(0.1, 0.0, 0.0),
-                1.0,
-                Material {
-                    albedo: Color::rgb(0.0, 0.5, 0.0),
-                    roughness: 0.1,
-                    //metallic: 1.0,
-                    ..Default::default()
-                } 
-            )
-        );
-
         let point_light = PointLight::new(
             Vec3::new(-0.5, -2.0, 0.0),
             Vec3::new(0.0, -1.0, -1.0),
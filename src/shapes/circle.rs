@@ -1,6 +1,6 @@
-use super::Shape;
+use super::{Shape, Hit};
 use crate::{
-    util::{Material, Color, vec::*},
+    util::{Aabb, Material, Color, Ray, vec::*},
     renderer::Vertex
 };
 
@@ -17,9 +17,13 @@ impl Shape for Circle {
     fn get_surface_color(&self) -> Color { self.material.albedo }
     fn get_position(&self) -> Vec3 { self.position }
     fn get_radius(&self) -> Option<f32> { Some(self.radius) }
-    fn get_material(&self) -> Material { self.material }
+    fn get_material(&self) -> Material { self.material.clone() }
 
     fn get_vertices(&self) -> &[Vertex] {
         &[]
     }
+
+    // Flat shapes have no volume for rays to hit
+    fn intersect(&self, _ray: &Ray) -> Option<Hit> { None }
+    fn get_bounds(&self) -> Aabb { Aabb::empty() }
 }
\ No newline at end of file
@@ -0,0 +1,339 @@
+use super::{Shape, Hit};
+use crate::{
+    environment::bvh::Bvh,
+    util::{
+        Aabb,
+        Material,
+        Color,
+        Ray,
+        vec::*
+    },
+    renderer::Vertex
+};
+
+/// Deepest the curve is ever split when searching for a hit
+const MAX_DEPTH: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CurveType {
+    /// Flat strip which always faces the incoming ray
+    Ribbon,
+    /// Round tube, shaded like a cylinder around the curve
+    Cylinder
+}
+
+/// Cubic Bézier curve with a width that changes linearly from start to end
+#[derive(Debug, Clone)]
+pub struct Curve {
+    pub control_points: [Vec3; 4],
+    /// Width at the start and at the end of the curve
+    pub widths: (f32, f32),
+    pub curve_type: CurveType,
+    pub material: Material,
+    /// Amount of times to split the curve before treating it as straight
+    depth: u32
+}
+
+/// Closest hit found so far, in the space where the ray starts at the origin looking down +Z
+#[derive(Clone, Copy)]
+struct CurveHit {
+    depth: f32,
+    u: f32,
+    /// Distance from the middle of the curve, -1.0 to 1.0 from edge to edge
+    offset: f32
+}
+
+impl Curve {
+    pub fn new(control_points: [Vec3; 4], widths: (f32, f32), curve_type: CurveType, material: Material) -> Self {
+        // Split until the pieces are flat to within a fraction of the width
+        let curvature = (0..2)
+            .map(|i| {
+                let [a, b, c] = [control_points[i], control_points[i + 1], control_points[i + 2]];
+                let d = a.sub(&b.mul_by(2.0)).add(&c);
+                d.x.abs().max(d.y.abs()).max(d.z.abs())
+            })
+            .fold(0.0, f32::max);
+
+        let epsilon = widths.0.max(widths.1) * 0.05;
+        let depth = if curvature <= 0.0 || epsilon <= 0.0 {
+            0
+        } else {
+            ((std::f32::consts::SQRT_2 * 6.0 * curvature / (8.0 * epsilon)).log2() / 2.0)
+                .clamp(0.0, MAX_DEPTH as f32) as u32
+        };
+
+        Self {
+            control_points,
+            widths,
+            curve_type,
+            material,
+            depth
+        }
+    }
+
+    pub fn get_width(&self, u: f32) -> f32 {
+        self.widths.0 * (1.0 - u) + self.widths.1 * u
+    }
+
+    pub fn get_point(&self, u: f32) -> Vec3 {
+        evaluate(&self.control_points, u)
+    }
+
+    pub fn get_tangent(&self, u: f32) -> Vec3 {
+        let [a, b, c, d] = self.control_points;
+        let ab = b.sub(&a);
+        let bc = c.sub(&b);
+        let cd = d.sub(&c);
+
+        ab.mul_by(3.0 * (1.0 - u) * (1.0 - u))
+            .add(&bc.mul_by(6.0 * (1.0 - u) * u))
+            .add(&cd.mul_by(3.0 * u * u))
+    }
+
+    /// Search the curve pieces whose bounds contain the ray, keeping the closest hit
+    fn intersect_recursive(&self, points: &[Vec3; 4], u_range: (f32, f32), depth: u32, closest: &mut Option<CurveHit>) {
+        let half_width = self.get_width(u_range.0).max(self.get_width(u_range.1)) / 2.0;
+        let max_depth = closest.map_or(f32::INFINITY, |hit| hit.depth);
+        let bounds = Aabb::from_points(points).pad(half_width);
+
+        // The ray runs along +Z from the origin, skip pieces it can't pass through
+        if bounds.min.x > 0.0 || bounds.max.x < 0.0
+            || bounds.min.y > 0.0 || bounds.max.y < 0.0
+            || bounds.max.z < 0.0 || bounds.min.z > max_depth {
+            return;
+        }
+
+        if depth > 0 {
+            let (first, second) = split(points);
+            let middle = (u_range.0 + u_range.1) / 2.0;
+
+            self.intersect_recursive(&first, (u_range.0, middle), depth - 1, closest);
+            self.intersect_recursive(&second, (middle, u_range.1), depth - 1, closest);
+            return;
+        }
+
+        // Treat the piece as a straight segment, rejecting the ray if it passes
+        // beyond the planes perpendicular to the curve at either end
+        let [a, b, c, d] = *points;
+        if (b.y - a.y) * -a.y + a.x * (a.x - b.x) < 0.0 { return; }
+        if (c.y - d.y) * -d.y + d.x * (d.x - c.x) < 0.0 { return; }
+
+        let segment = Vec2::new(d.x - a.x, d.y - a.y);
+        let length = segment.squared_magnitude();
+        if length == 0.0 { return; }
+
+        // Closest point on the segment to the ray
+        let w = ((-a.x * segment.x - a.y * segment.y) / length).clamp(0.0, 1.0);
+        let u = u_range.0 + (u_range.1 - u_range.0) * w;
+        let point = evaluate(points, w);
+
+        let half_width = self.get_width(u) / 2.0;
+        let distance = (point.x * point.x + point.y * point.y).sqrt();
+        if distance > half_width || point.z <= 0.0 || point.z >= max_depth { return; }
+
+        // Which side of the curve the ray passes on
+        let side = segment.x * -point.y + segment.y * point.x;
+        let offset = if side > 0.0 { distance } else { -distance } / half_width;
+
+        *closest = Some(CurveHit { depth: point.z, u, offset });
+    }
+}
+
+impl Shape for Curve {
+    fn has_radius(&self) -> bool { false }
+    fn is_3d(&self) -> bool { true }
+    fn get_surface_color(&self) -> Color { self.material.albedo }
+    fn get_position(&self) -> Vec3 { self.get_point(0.5) }
+    fn get_radius(&self) -> Option<f32> { None }
+    fn get_material(&self) -> Material { self.material.clone() }
+
+    fn get_vertices(&self) -> &[Vertex] {
+        &[]
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<Hit> {
+        // Move into a space where the ray starts at the origin and looks down +Z
+        let direction_length = ray.direction.magnitude();
+        let forward = ray.direction.div_by(direction_length);
+        let (right, up) = get_basis(&forward);
+
+        let points = self.control_points.map(|point| {
+            let relative = point.sub(&ray.position);
+            Vec3::new(relative.dot(&right), relative.dot(&up), relative.dot(&forward))
+        });
+
+        let mut closest = None;
+        self.intersect_recursive(&points, (0.0, 1.0), self.depth, &mut closest);
+        let CurveHit { depth, u, offset } = closest?;
+
+        let tangent = self.get_tangent(u).normalize();
+        let without_tangent = |vector: Vec3| vector.sub(&tangent.mul_by(vector.dot(&tangent)));
+
+        let (depth, normal) = match self.curve_type {
+            CurveType::Ribbon => (depth, without_tangent(forward.invert())),
+            CurveType::Cylinder => {
+                // Move the hit from the middle plane onto the front of the tube
+                let half_width = self.get_width(u) / 2.0;
+                let front = depth - half_width * (1.0 - offset * offset).max(0.0).sqrt();
+
+                // Rays starting inside the tube would hit its front behind them, keep the middle plane instead
+                let depth = if front > 0.0 { front } else { depth };
+                let position = ray.position.add(&forward.mul_by(depth));
+
+                (depth, without_tangent(position.sub(&self.get_point(u))))
+            }
+        };
+
+        let distance = depth / direction_length;
+        let normal = if normal.squared_magnitude() > 0.0 { normal.normalize() } else { forward.invert() };
+
+        Some(Hit::new(distance, ray.get_point(distance), normal))
+    }
+
+    fn get_bounds(&self) -> Aabb {
+        Aabb::from_points(&self.control_points).pad(self.widths.0.max(self.widths.1) / 2.0)
+    }
+}
+
+/// Many curves sharing one material, intersected through their own [Bvh].
+/// Prefer this over adding every strand of hair or fur to the scene on its own
+pub struct Curves {
+    pub curves: Vec<Curve>,
+    pub material: Material,
+    bvh: Bvh
+}
+
+impl Curves {
+    /// The material of the individual curves is replaced by `material`
+    pub fn new(curves: Vec<Curve>, material: Material) -> Self {
+        let bounds: Vec<Aabb> = curves.iter().map(Curve::get_bounds).collect();
+
+        Self {
+            curves,
+            material,
+            bvh: Bvh::new(&bounds)
+        }
+    }
+}
+
+impl Shape for Curves {
+    fn has_radius(&self) -> bool { false }
+    fn is_3d(&self) -> bool { true }
+    fn get_surface_color(&self) -> Color { self.material.albedo }
+    fn get_position(&self) -> Vec3 { self.bvh.get_bounds().get_centroid() }
+    fn get_radius(&self) -> Option<f32> { None }
+    fn get_material(&self) -> Material { self.material.clone() }
+
+    fn get_vertices(&self) -> &[Vertex] {
+        &[]
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<Hit> {
+        self.bvh
+            .intersect(ray, |i| self.curves[i].intersect(ray))
+            .map(|(_, hit)| hit)
+    }
+
+    fn get_bounds(&self) -> Aabb {
+        self.bvh.get_bounds()
+    }
+}
+
+fn evaluate(points: &[Vec3; 4], u: f32) -> Vec3 {
+    let [a, b, c, d] = points;
+    let v = 1.0 - u;
+
+    a.mul_by(v * v * v)
+        .add(&b.mul_by(3.0 * v * v * u))
+        .add(&c.mul_by(3.0 * v * u * u))
+        .add(&d.mul_by(u * u * u))
+}
+
+/// Split a curve in half with de Casteljau's algorithm
+fn split(points: &[Vec3; 4]) -> ([Vec3; 4], [Vec3; 4]) {
+    let midpoint = |a: &Vec3, b: &Vec3| a.add(b).mul_by(0.5);
+    let [a, b, c, d] = points;
+
+    let ab = midpoint(a, b);
+    let bc = midpoint(b, c);
+    let cd = midpoint(c, d);
+    let abc = midpoint(&ab, &bc);
+    let bcd = midpoint(&bc, &cd);
+    let middle = midpoint(&abc, &bcd);
+
+    ([*a, ab, abc, middle], [middle, bcd, cd, *d])
+}
+
+/// Two unit vectors perpendicular to `forward` and each other
+fn get_basis(forward: &Vec3) -> (Vec3, Vec3) {
+    let helper = if forward.x.abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+    let right = helper.cross(forward).normalize();
+    let up = forward.cross(&right);
+
+    (right, up)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn straight(curve_type: CurveType) -> Curve {
+        Curve::new(
+            [Vec3::new(-1.0, 0.0, -5.0), Vec3::new(-0.3, 0.0, -5.0), Vec3::new(0.3, 0.0, -5.0), Vec3::new(1.0, 0.0, -5.0)],
+            (0.4, 0.4),
+            curve_type,
+            Material::default()
+        )
+    }
+
+    #[test]
+    fn cylinder_hit() {
+        let curve = straight(CurveType::Cylinder);
+        let hit = curve.intersect(&Ray::new(Vec3::new(0.2, 0.1, 0.0), Vec3::new(0.0, 0.0, -2.0))).unwrap();
+
+        // A tube of radius 0.2 seen 0.1 off its center
+        let depth = 0.2f32.powi(2) - 0.1f32.powi(2);
+        assert!((hit.distance * 2.0 - (5.0 - depth.sqrt())).abs() < 1e-3);
+        assert!(hit.normal.sub(&Vec3::new(0.0, 0.5, depth.sqrt() / 0.2)).magnitude() < 1e-3);
+
+        assert!(curve.intersect(&Ray::new(Vec3::new(0.2, 0.3, 0.0), Vec3::new(0.0, 0.0, -1.0))).is_none());
+        assert!(curve.intersect(&Ray::new(Vec3::new(1.5, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0))).is_none());
+    }
+
+    #[test]
+    fn cylinder_hit_from_inside() {
+        let curve = straight(CurveType::Cylinder);
+
+        // Starting 0.1 in front of the center line of a tube of radius 0.2
+        let hit = curve.intersect(&Ray::new(Vec3::new(0.2, 0.0, -4.9), Vec3::new(0.0, 0.0, -1.0))).unwrap();
+        assert!(hit.distance > 0.0);
+        assert!((hit.distance - 0.1).abs() < 1e-3);
+    }
+
+    #[test]
+    fn ribbon_faces_the_ray() {
+        let curve = straight(CurveType::Ribbon);
+        let hit = curve.intersect(&Ray::new(Vec3::new(0.0, 0.1, 0.0), Vec3::new(0.0, 0.0, -1.0))).unwrap();
+
+        assert!((hit.distance - 5.0).abs() < 1e-3);
+        assert!(hit.normal.sub(&Vec3::new(0.0, 0.0, 1.0)).magnitude() < 1e-3);
+    }
+
+    #[test]
+    fn bent_curve() {
+        let curve = Curve::new(
+            [Vec3::new(-1.0, 0.0, 0.0), Vec3::new(-1.0, 2.0, 0.0), Vec3::new(1.0, 2.0, 0.0), Vec3::new(1.0, 0.0, 0.0)],
+            (0.1, 0.05),
+            CurveType::Cylinder,
+            Material::default()
+        );
+
+        // The top of the arch is at y = 1.5
+        let hit = curve.intersect(&Ray::new(Vec3::new(0.0, 1.5, 5.0), Vec3::new(0.0, 0.0, -1.0))).unwrap();
+        assert!((hit.position.z - 0.0375).abs() < 1e-2);
+
+        let strands = Curves::new(vec![curve.clone(), straight(CurveType::Cylinder)], Material::default());
+        let hit = strands.intersect(&Ray::new(Vec3::new(0.0, 1.5, 5.0), Vec3::new(0.0, 0.0, -1.0))).unwrap();
+        assert!((hit.position.z - 0.0375).abs() < 1e-2);
+    }
+}
\ No newline at end of file
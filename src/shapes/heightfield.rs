@@ -0,0 +1,305 @@
+use std::path::Path;
+
+use super::{Shape, Hit, intersect_triangle};
+use crate::{
+    util::{
+        Aabb,
+        Material,
+        Color,
+        Ray,
+        vec::*
+    },
+    renderer::Vertex
+};
+
+/// Terrain made from a grid of elevation samples, spread over the XZ plane and rising along Y
+#[derive(Debug, Clone)]
+pub struct Heightfield {
+    /// Center of the base of the terrain
+    pub position: Vec3,
+    pub material: Material,
+    /// Size of the terrain along X and Z
+    extent: Vec2,
+    /// Height of a sample with a value of 1.0
+    height_scale: f32,
+    /// Elevation samples, row by row along X
+    samples: Vec<f32>,
+    /// Amount of samples along X and Z
+    resolution: (usize, usize),
+    normals: Vec<Vec3>,
+    /// Lowest and highest scaled sample, kept so rays don't scan every sample
+    height_range: (f32, f32)
+}
+
+impl Heightfield {
+    /// Creates a terrain from `samples` (usually 0.0 - 1.0) laid out row by row,
+    /// `resolution` is the amount of samples along X and Z and needs to be at least 2 by 2
+    pub fn new(samples: Vec<f32>, resolution: (usize, usize), material: Material) -> Self {
+        assert!(resolution.0 >= 2 && resolution.1 >= 2, "Heightfields need at least 2x2 samples");
+        assert_eq!(samples.len(), resolution.0 * resolution.1, "Sample count must match the resolution");
+
+        let mut heightfield = Self {
+            position: Vec3::new(0.0, 0.0, 0.0),
+            material,
+            extent: Vec2::new(resolution.0 as f32 - 1.0, resolution.1 as f32 - 1.0),
+            height_scale: 1.0,
+            samples,
+            resolution,
+            normals: Vec::new(),
+            height_range: (0.0, 0.0)
+        };
+
+        heightfield.update_height_range();
+        heightfield.update_normals();
+        heightfield
+    }
+
+    /// Loads a grayscale image (e.g. a DEM export) where black is the lowest
+    /// and white the highest point, the image's rows run along Z
+    pub fn from_image<P: AsRef<Path>>(path: P, material: Material) -> image::ImageResult<Self> {
+        let image = image::open(path)?.to_luma32f();
+        let resolution = (image.width() as usize, image.height() as usize);
+        let samples = image.pixels().map(|pixel| pixel.0[0]).collect();
+
+        Ok(Self::new(samples, resolution, material))
+    }
+
+    pub fn set_position(mut self, position: Vec3) -> Self {
+        self.position = position;
+        self
+    }
+
+    /// Set the size of the terrain along X and Z
+    pub fn set_extent(mut self, width: f32, depth: f32) -> Self {
+        self.extent = Vec2::new(width, depth);
+        self.update_normals();
+        self
+    }
+
+    /// Set how high samples with a value of 1.0 rise
+    pub fn set_height_scale(mut self, height_scale: f32) -> Self {
+        self.height_scale = height_scale;
+        self.update_height_range();
+        self.update_normals();
+        self
+    }
+
+    fn get_cell_size(&self) -> Vec2 {
+        Vec2::new(
+            self.extent.x / (self.resolution.0 - 1) as f32,
+            self.extent.y / (self.resolution.1 - 1) as f32
+        )
+    }
+
+    fn get_corner(&self) -> Vec3 {
+        self.position.sub(&Vec3::new(self.extent.x / 2.0, 0.0, self.extent.y / 2.0))
+    }
+
+    fn get_height(&self, x: usize, z: usize) -> f32 {
+        self.samples[x + z * self.resolution.0] * self.height_scale
+    }
+
+    /// Position of the sample at the given grid coordinates relative to the corner
+    fn get_local_point(&self, x: usize, z: usize) -> Vec3 {
+        let cell_size = self.get_cell_size();
+        Vec3::new(x as f32 * cell_size.x, self.get_height(x, z), z as f32 * cell_size.y)
+    }
+
+    /// Smooth normals from the slope between neighbouring samples
+    fn update_normals(&mut self) {
+        let (width, depth) = self.resolution;
+        let cell_size = self.get_cell_size();
+
+        self.normals = (0..width * depth)
+            .map(|i| {
+                let (x, z) = (i % width, i / width);
+                let (left, right) = (x.saturating_sub(1), (x + 1).min(width - 1));
+                let (back, front) = (z.saturating_sub(1), (z + 1).min(depth - 1));
+
+                let slope_x = (self.get_height(right, z) - self.get_height(left, z))
+                    / ((right - left) as f32 * cell_size.x);
+                let slope_z = (self.get_height(x, front) - self.get_height(x, back))
+                    / ((front - back) as f32 * cell_size.y);
+
+                Vec3::new(-slope_x, 1.0, -slope_z).normalize()
+            })
+            .collect();
+    }
+
+    fn update_height_range(&mut self) {
+        self.height_range = self.samples
+            .iter()
+            .map(|sample| sample * self.height_scale)
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), height| (min.min(height), max.max(height)));
+    }
+
+    /// Test the two triangles of the cell whose lowest corner is at `x`, `z`
+    fn intersect_cell(&self, ray: &Ray, x: usize, z: usize) -> Option<Hit> {
+        let corners = [(x, z), (x, z + 1), (x + 1, z + 1), (x + 1, z)];
+        let points = corners.map(|(x, z)| self.get_local_point(x, z));
+        let normals = corners.map(|(x, z)| self.normals[x + z * self.resolution.0]);
+
+        let mut closest: Option<Hit> = None;
+
+        for [a, b, c] in [[0, 1, 2], [0, 2, 3]] {
+            let Some((u, v, distance)) = intersect_triangle(ray, &points[a], &points[b], &points[c]) else { continue; };
+            if closest.as_ref().is_some_and(|hit| hit.distance <= distance) { continue; }
+
+            let normal = normals[a]
+                .mul_by(1.0 - u - v)
+                .add(&normals[b].mul_by(u))
+                .add(&normals[c].mul_by(v))
+                .normalize();
+
+            // UVs span the whole grid
+            let position = ray.get_point(distance);
+            let uv = Vec2::new(position.x / self.extent.x, position.z / self.extent.y);
+
+            // Along the grid axes, straightened against the normal by whoever uses them
+            let (dpdu, dpdv) = (Vec3::new(self.extent.x, 0.0, 0.0), Vec3::new(0.0, 0.0, self.extent.y));
+
+            closest = Some(Hit::new(distance, position, normal).set_uv(uv).set_tangents(dpdu, dpdv));
+        }
+
+        closest
+    }
+}
+
+impl Shape for Heightfield {
+    fn has_radius(&self) -> bool { false }
+    fn is_3d(&self) -> bool { true }
+    fn get_surface_color(&self) -> Color { self.material.albedo }
+    fn get_position(&self) -> Vec3 { self.position }
+    fn get_radius(&self) -> Option<f32> { None }
+    fn get_material(&self) -> Material { self.material.clone() }
+
+    fn get_vertices(&self) -> &[Vertex] {
+        &[]
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<Hit> {
+        // Work relative to the corner so cells start at 0, 0
+        let corner = self.get_corner();
+        let local_ray = Ray::new(ray.position.sub(&corner), ray.direction);
+
+        let (min_height, max_height) = self.height_range;
+        let bounds = Aabb::new(
+            Vec3::new(0.0, min_height, 0.0),
+            Vec3::new(self.extent.x, max_height, self.extent.y)
+        );
+
+        let inverse_direction = ray.get_inverse_direction();
+        let (enter, exit) = bounds.clip(&local_ray.position, &inverse_direction, f32::INFINITY)?;
+
+        // Walk through the cells under the ray in order (Amanatides & Woo),
+        // the first cell with a hit holds the closest one
+        let cell_size = self.get_cell_size();
+        let start = local_ray.get_point(enter);
+        let last_cell = (self.resolution.0 - 2, self.resolution.1 - 2);
+
+        let mut x = ((start.x / cell_size.x).max(0.0) as usize).min(last_cell.0);
+        let mut z = ((start.z / cell_size.y).max(0.0) as usize).min(last_cell.1);
+
+        let step_x: isize = if ray.direction.x >= 0.0 { 1 } else { -1 };
+        let step_z: isize = if ray.direction.z >= 0.0 { 1 } else { -1 };
+
+        let delta_x = (cell_size.x * inverse_direction.x).abs();
+        let delta_z = (cell_size.y * inverse_direction.z).abs();
+
+        let next_boundary = |cell: usize, step: isize, size: f32| (cell as isize + step.max(0)) as f32 * size;
+        let mut max_x = (next_boundary(x, step_x, cell_size.x) - local_ray.position.x) * inverse_direction.x;
+        let mut max_z = (next_boundary(z, step_z, cell_size.y) - local_ray.position.z) * inverse_direction.z;
+
+        loop {
+            if let Some(hit) = self.intersect_cell(&local_ray, x, z) {
+                return Some(Hit { position: hit.position.add(&corner), ..hit });
+            }
+
+            // The ray leaves the bounds, over or under the terrain, before reaching the next cell
+            if max_x.min(max_z) > exit { break; }
+
+            if max_x < max_z {
+                let Some(next) = x.checked_add_signed(step_x).filter(|&x| x <= last_cell.0) else { break; };
+                x = next;
+                max_x += delta_x;
+            } else {
+                let Some(next) = z.checked_add_signed(step_z).filter(|&z| z <= last_cell.1) else { break; };
+                z = next;
+                max_z += delta_z;
+            }
+        }
+
+        None
+    }
+
+    fn get_bounds(&self) -> Aabb {
+        let (min_height, max_height) = self.height_range;
+        let corner = self.get_corner();
+
+        Aabb::new(
+            corner.add(&Vec3::new(0.0, min_height, 0.0)),
+            corner.add(&Vec3::new(self.extent.x, max_height, self.extent.y))
+        )
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hills() -> Heightfield {
+        let resolution = (40, 30);
+        let samples = (0..resolution.0 * resolution.1)
+            .map(|i| {
+                let (x, z) = ((i % resolution.0) as f32, (i / resolution.0) as f32);
+                ((x * 0.4).sin() * (z * 0.3).cos() + 1.0) / 2.0
+            })
+            .collect();
+
+        Heightfield::new(samples, resolution, Material::default())
+            .set_extent(20.0, 15.0)
+            .set_height_scale(3.0)
+            .set_position(Vec3::new(1.0, -2.0, 0.5))
+    }
+
+    #[test]
+    fn flat_ground() {
+        let ground = Heightfield::new(vec![0.5; 9], (3, 3), Material::default())
+            .set_height_scale(2.0);
+
+        let hit = ground.intersect(&Ray::new(Vec3::new(0.3, 5.0, -0.2), Vec3::new(0.0, -1.0, 0.0))).unwrap();
+        assert!((hit.distance - 4.0).abs() < 1e-4);
+        assert!(hit.normal.sub(&Vec3::new(0.0, 1.0, 0.0)).magnitude() < 1e-4);
+
+        assert!(ground.intersect(&Ray::new(Vec3::new(5.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0))).is_none());
+    }
+
+    #[test]
+    fn traversal_matches_brute_force() {
+        let terrain = hills();
+        let corner = terrain.get_corner();
+
+        for i in 0..200 {
+            let angle = i as f32 * 0.61;
+            let ray = Ray::new(
+                Vec3::new(angle.cos() * 15.0, 4.0, angle.sin() * 12.0),
+                Vec3::new(-angle.cos(), -0.2 - (i % 5) as f32 * 0.1, -angle.sin() + 0.1)
+            );
+
+            let local_ray = Ray::new(ray.position.sub(&corner), ray.direction);
+            let expected = (0..terrain.resolution.0 - 1)
+                .flat_map(|x| (0..terrain.resolution.1 - 1).map(move |z| (x, z)))
+                .filter_map(|(x, z)| terrain.intersect_cell(&local_ray, x, z))
+                .map(|hit| hit.distance)
+                .min_by(f32::total_cmp);
+
+            let found = terrain.intersect(&ray).map(|hit| hit.distance);
+
+            match (found, expected) {
+                (Some(found), Some(expected)) => assert!((found - expected).abs() < 1e-3, "{found} != {expected}"),
+                (found, expected) => assert_eq!(found, expected)
+            }
+        }
+    }
+}
\ No newline at end of file
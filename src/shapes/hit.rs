@@ -0,0 +1,117 @@
+use crate::util::{Ray, vec::*};
+
+/// Information about where a ray intersected a [Shape](super::Shape)
+#[derive(Debug, Clone)]
+pub struct Hit {
+    /// Distance along the ray, in multiples of its direction
+    pub distance: f32,
+    pub position: Vec3,
+    /// Unit surface normal facing away from the shape
+    pub normal: Vec3,
+    /// Texture coordinates, (0, 0) being the top left corner of images
+    pub uv: Vec2,
+    /// How the position changes along `u` and `v`, zero when the shape has no UV layout
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+    /// Axes of the ellipse the ray covers in UV space, zero for a single point
+    pub footprint: [Vec2; 2],
+    /// Which of the shape's materials applies at the hit when they differ across it,
+    /// looked up through [Shape::get_hit_material](super::Shape::get_hit_material)
+    pub material_index: Option<usize>
+}
+
+impl Hit {
+    pub fn new(distance: f32, position: Vec3, normal: Vec3) -> Self {
+        Self {
+            distance,
+            position,
+            normal,
+            uv: Vec2::new(0.0, 0.0),
+            dpdu: Vec3::new(0.0, 0.0, 0.0),
+            dpdv: Vec3::new(0.0, 0.0, 0.0),
+            footprint: [Vec2::new(0.0, 0.0); 2],
+            material_index: None
+        }
+    }
+
+    pub fn set_uv(mut self, uv: Vec2) -> Self {
+        self.uv = uv;
+        self
+    }
+
+    /// Sets the tangent frame used by normal and bump maps
+    pub fn set_tangents(mut self, dpdu: Vec3, dpdv: Vec3) -> Self {
+        self.dpdu = dpdu;
+        self.dpdv = dpdv;
+        self
+    }
+
+    /// Whether the ray arrived from outside the shape, against the normal
+    pub fn is_front_face(&self, ray: &Ray) -> bool {
+        ray.direction.dot(&self.normal) < 0.0
+    }
+
+    /// Projects the cone of the ray that hit onto the surface and into UV space.
+    /// The cone stretches along the ray's direction the more it grazes the surface
+    pub fn update_footprint(&mut self, ray: &Ray) {
+        let width = ray.get_cone_width(self.distance);
+        if width <= 0.0 { return; }
+
+        // Least squares solve through the Gram matrix, as the derivatives may not be perpendicular
+        let (uu, uv, vv) = (self.dpdu.dot(&self.dpdu), self.dpdu.dot(&self.dpdv), self.dpdv.dot(&self.dpdv));
+        let determinant = uu * vv - uv * uv;
+        if determinant.abs() < 1e-12 { return; }
+
+        let to_uv = |vector: Vec3| {
+            let (along_u, along_v) = (vector.dot(&self.dpdu), vector.dot(&self.dpdv));
+            Vec2::new((vv * along_u - uv * along_v) / determinant, (uu * along_v - uv * along_u) / determinant)
+        };
+
+        let direction = ray.direction.normalize();
+        let cos_theta = direction.dot(&self.normal).abs().max(1e-2);
+
+        let along = direction.sub(&self.normal.mul_by(direction.dot(&self.normal)));
+        let along = if along.magnitude() > 1e-6 { along.normalize() } else { self.dpdu.normalize() };
+        let across = self.normal.cross(&along);
+
+        self.footprint = [to_uv(along.mul_by(width / cos_theta)), to_uv(across.mul_by(width))];
+    }
+
+    pub fn set_material_index(mut self, index: usize) -> Self {
+        self.material_index = Some(index);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        shapes::{Mesh, Shape},
+        util::{Material, Ray, vec::*}
+    };
+
+    #[test]
+    fn footprint_stretches_at_grazing_angles() {
+        // Quad of 2x2 units covering the whole UV square
+        let quad = Mesh::new(
+            vec![Vec3::new(-1.0, 0.0, -1.0), Vec3::new(1.0, 0.0, -1.0), Vec3::new(1.0, 0.0, 1.0), Vec3::new(-1.0, 0.0, 1.0)],
+            vec![[0, 2, 1], [0, 3, 2]],
+            Material::default()
+        ).set_uvs(vec![Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0), Vec2::new(1.0, 1.0), Vec2::new(0.0, 1.0)]);
+
+        // Head on, a cone 0.2 wide covers a tenth of the UVs either way
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0)).set_cone(0.0, 0.2);
+        let mut hit = quad.intersect(&ray).unwrap();
+        hit.update_footprint(&ray);
+        assert!((hit.footprint[0].magnitude() - 0.1).abs() < 1e-5);
+        assert!((hit.footprint[1].magnitude() - 0.1).abs() < 1e-5);
+
+        // At 60° from the normal, it stretches twice as long along the ray
+        let direction = Vec3::new(3.0f32.sqrt(), -1.0, 0.0);
+        let ray = Ray::new(Vec3::new(-3.0f32.sqrt(), 1.0, 0.0), direction).set_cone(0.0, 0.1);
+        let mut hit = quad.intersect(&ray).unwrap();
+        hit.update_footprint(&ray);
+        assert!((hit.footprint[0].x.abs() - 0.2).abs() < 1e-4);
+        assert!((hit.footprint[1].magnitude() - 0.1).abs() < 1e-4);
+    }
+}
\ No newline at end of file
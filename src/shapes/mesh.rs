@@ -0,0 +1,222 @@
+use super::{Shape, Hit, SurfaceSample};
+use crate::{
+    environment::bvh::Bvh,
+    util::{
+        Aabb,
+        Material,
+        Color,
+        Ray,
+        random,
+        vec::*
+    },
+    renderer::Vertex
+};
+
+/// Rays closer to parallel with a triangle than this are treated as missing it
+const PARALLEL_EPSILON: f32 = 1e-8;
+
+/// Triangle mesh with smooth shading, intersected through its own [Bvh]
+pub struct Mesh {
+    pub positions: Vec<Vec3>,
+    /// Per vertex normals, interpolated across each triangle
+    pub normals: Vec<Vec3>,
+    /// Indices into `positions` and `normals`, counter-clockwise when seen from the front
+    pub triangles: Vec<[usize; 3]>,
+    /// Per vertex texture coordinates, empty when the mesh has none
+    pub uvs: Vec<Vec2>,
+    pub material: Material,
+    bvh: Bvh,
+    /// Running total of the triangle areas, to pick triangles proportionally to their size
+    area_cdf: Vec<f32>
+}
+
+impl Mesh {
+    /// Creates a mesh with normals averaged from the triangles around every vertex
+    pub fn new(positions: Vec<Vec3>, triangles: Vec<[usize; 3]>, material: Material) -> Self {
+        let mut normals = vec![Vec3::new(0.0, 0.0, 0.0); positions.len()];
+
+        for &[a, b, c] in &triangles {
+            // Left unnormalized so bigger triangles weigh more
+            let face_normal = positions[b]
+                .sub(&positions[a])
+                .cross(&positions[c].sub(&positions[a]));
+
+            for i in [a, b, c] {
+                normals[i].add_mut(&face_normal);
+            }
+        }
+
+        for normal in normals.iter_mut() {
+            normal.normalize_mut();
+        }
+
+        Self::with_normals(positions, normals, triangles, material)
+    }
+
+    /// Creates a mesh using the given per vertex normals
+    pub fn with_normals(
+        positions: Vec<Vec3>,
+        normals: Vec<Vec3>,
+        triangles: Vec<[usize; 3]>,
+        material: Material
+    ) -> Self {
+        let mut mesh = Self {
+            positions,
+            normals,
+            triangles,
+            uvs: Vec::new(),
+            material,
+            bvh: Bvh::default(),
+            area_cdf: Vec::new()
+        };
+
+        mesh.update_bvh();
+        mesh
+    }
+
+    /// Gives every vertex texture coordinates
+    pub fn set_uvs(mut self, uvs: Vec<Vec2>) -> Self {
+        assert_eq!(uvs.len(), self.positions.len(), "Every vertex needs a UV");
+        self.uvs = uvs;
+        self
+    }
+
+    /// Rebuilds the acceleration structure, call after editing `positions` or `triangles`
+    pub fn update_bvh(&mut self) {
+        let bounds: Vec<Aabb> = self.triangles
+            .iter()
+            .map(|triangle| self.get_triangle_bounds(triangle))
+            .collect();
+
+        self.bvh = Bvh::new(&bounds);
+
+        let mut total = 0.0;
+        self.area_cdf = self.triangles
+            .iter()
+            .map(|&[a, b, c]| {
+                total += self.get_face_normal(a, b, c).magnitude() * 0.5;
+                total
+            })
+            .collect();
+    }
+
+    /// Unnormalized normal of the triangle, its length being twice the triangle's area
+    fn get_face_normal(&self, a: usize, b: usize, c: usize) -> Vec3 {
+        self.positions[b]
+            .sub(&self.positions[a])
+            .cross(&self.positions[c].sub(&self.positions[a]))
+    }
+
+    fn get_triangle_bounds(&self, &[a, b, c]: &[usize; 3]) -> Aabb {
+        Aabb::from_points(&[self.positions[a], self.positions[b], self.positions[c]])
+    }
+
+    fn intersect_triangle(&self, ray: &Ray, &[a, b, c]: &[usize; 3]) -> Option<Hit> {
+        let (u, v, distance) = intersect_triangle(
+            ray,
+            &self.positions[a],
+            &self.positions[b],
+            &self.positions[c]
+        )?;
+
+        // Interpolate the vertex normals with the barycentric coordinates
+        let normal = self.normals[a]
+            .mul_by(1.0 - u - v)
+            .add(&self.normals[b].mul_by(u))
+            .add(&self.normals[c].mul_by(v))
+            .normalize();
+
+        let hit = Hit::new(distance, ray.get_point(distance), normal);
+        if self.uvs.is_empty() { return Some(hit); }
+
+        let uv = self.uvs[a]
+            .mul_by(1.0 - u - v)
+            .add(&self.uvs[b].mul_by(u))
+            .add(&self.uvs[c].mul_by(v));
+
+        // Solve for the position derivatives from how UVs change along two edges
+        let (uv_ab, uv_ac) = (self.uvs[b].sub(&self.uvs[a]), self.uvs[c].sub(&self.uvs[a]));
+        let (edge_ab, edge_ac) = (self.positions[b].sub(&self.positions[a]), self.positions[c].sub(&self.positions[a]));
+        let determinant = uv_ab.x * uv_ac.y - uv_ab.y * uv_ac.x;
+        if determinant.abs() < PARALLEL_EPSILON { return Some(hit.set_uv(uv)); }
+
+        let dpdu = edge_ab.mul_by(uv_ac.y).sub(&edge_ac.mul_by(uv_ab.y)).div_by(determinant);
+        let dpdv = edge_ac.mul_by(uv_ab.x).sub(&edge_ab.mul_by(uv_ac.x)).div_by(determinant);
+
+        Some(hit.set_uv(uv).set_tangents(dpdu, dpdv))
+    }
+}
+
+impl Shape for Mesh {
+    fn has_radius(&self) -> bool { false }
+    fn is_3d(&self) -> bool { true }
+    fn get_surface_color(&self) -> Color { self.material.albedo }
+    fn get_position(&self) -> Vec3 { self.bvh.get_bounds().get_centroid() }
+    fn get_radius(&self) -> Option<f32> { None }
+    fn get_material(&self) -> Material { self.material.clone() }
+
+    fn get_vertices(&self) -> &[Vertex] {
+        &[]
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<Hit> {
+        self.bvh
+            .intersect(ray, |i| self.intersect_triangle(ray, &self.triangles[i]))
+            .map(|(_, hit)| hit)
+    }
+
+    fn get_bounds(&self) -> Aabb {
+        self.bvh.get_bounds()
+    }
+
+    fn sample_surface(&self) -> Option<SurfaceSample> {
+        let total = *self.area_cdf.last()?;
+        if total <= 0.0 { return None; }
+
+        let target = random() * total;
+        let index = self.area_cdf.partition_point(|&area| area <= target).min(self.triangles.len() - 1);
+        let [a, b, c] = self.triangles[index];
+
+        // Uniform barycentric coordinates, the square root keeps points from bunching up at `a`
+        let root = random().sqrt();
+        let u = root * random();
+        let v = root - u;
+
+        let position = self.positions[a]
+            .mul_by(1.0 - root)
+            .add(&self.positions[b].mul_by(u))
+            .add(&self.positions[c].mul_by(v));
+
+        Some(SurfaceSample {
+            position,
+            normal: self.get_face_normal(a, b, c).normalize(),
+            pdf: 1.0 / total
+        })
+    }
+}
+
+/// Möller–Trumbore ray/triangle intersection.
+/// Returns the barycentric coordinates of `b` and `c` and the distance along the ray
+pub fn intersect_triangle(ray: &Ray, a: &Vec3, b: &Vec3, c: &Vec3) -> Option<(f32, f32, f32)> {
+    let edge_ab = b.sub(a);
+    let edge_ac = c.sub(a);
+
+    let p = ray.direction.cross(&edge_ac);
+    let determinant = edge_ab.dot(&p);
+    if determinant.abs() < PARALLEL_EPSILON { return None; }
+
+    let inverse_determinant = 1.0 / determinant;
+    let t = ray.position.sub(a);
+
+    let u = t.dot(&p) * inverse_determinant;
+    if !(0.0..=1.0).contains(&u) { return None; }
+
+    let q = t.cross(&edge_ab);
+    let v = ray.direction.dot(&q) * inverse_determinant;
+    if v < 0.0 || u + v > 1.0 { return None; }
+
+    let distance = edge_ac.dot(&q) * inverse_determinant;
+    if distance <= 0.0 { return None; }
+
+    Some((u, v, distance))
+}
\ No newline at end of file
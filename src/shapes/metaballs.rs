@@ -0,0 +1,240 @@
+use super::{Shape, Hit};
+use crate::{
+    util::{
+        Aabb,
+        Material,
+        Color,
+        Ray,
+        vec::*
+    },
+    renderer::Vertex
+};
+
+/// Steps taken per radius of the smallest ball when searching for the surface
+const STEPS_PER_RADIUS: f32 = 8.0;
+/// Bisection steps used to refine a hit once the surface has been crossed
+const REFINE_STEPS: u32 = 24;
+
+/// How the influence of a ball fades from 1.0 at its center to 0.0 at its radius
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Falloff {
+    /// `(1 - r²)³`
+    Polynomial,
+    /// Wyvill's soft object kernel `1 - 4/9 r⁶ + 17/9 r⁴ - 22/9 r²`
+    Wyvill
+}
+
+impl Falloff {
+    /// Value and derivative of the kernel for `squared`, the squared distance divided by the squared radius
+    fn evaluate(&self, squared: f32) -> (f32, f32) {
+        if squared >= 1.0 { return (0.0, 0.0); }
+
+        let s = squared;
+        match self {
+            Falloff::Polynomial => ((1.0 - s).powi(3), -3.0 * (1.0 - s).powi(2)),
+            Falloff::Wyvill => (
+                1.0 - 4.0 / 9.0 * s * s * s + 17.0 / 9.0 * s * s - 22.0 / 9.0 * s,
+                -4.0 / 3.0 * s * s + 34.0 / 9.0 * s - 22.0 / 9.0
+            )
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Metaball {
+    pub position: Vec3,
+    /// Distance at which the ball stops having any influence
+    pub radius: f32,
+    pub weight: f32
+}
+
+/// Blobby surface where the summed influence of all balls reaches `threshold`
+#[derive(Debug, Clone)]
+pub struct Metaballs {
+    pub balls: Vec<Metaball>,
+    pub threshold: f32,
+    pub falloff: Falloff,
+    pub material: Material
+}
+
+impl Metaballs {
+    pub fn new(threshold: f32, material: Material) -> Self {
+        Self {
+            balls: Vec::new(),
+            threshold,
+            falloff: Falloff::Wyvill,
+            material
+        }
+    }
+
+    /// `radius` is how far the ball's influence reaches and has to be positive
+    pub fn add_ball(mut self, position: Vec3, radius: f32, weight: f32) -> Self {
+        assert!(radius > 0.0, "Metaballs need a positive radius");
+        self.balls.push(Metaball { position, radius, weight });
+        self
+    }
+
+    pub fn set_falloff(mut self, falloff: Falloff) -> Self {
+        self.falloff = falloff;
+        self
+    }
+
+    /// Summed influence of every ball at `point`
+    pub fn get_field(&self, point: &Vec3) -> f32 {
+        self.balls
+            .iter()
+            .map(|ball| {
+                let squared = point.sub(&ball.position).squared_magnitude() / (ball.radius * ball.radius);
+                ball.weight * self.falloff.evaluate(squared).0
+            })
+            .sum()
+    }
+
+    /// Direction the field grows fastest in at `point`
+    pub fn get_gradient(&self, point: &Vec3) -> Vec3 {
+        self.balls
+            .iter()
+            .fold(Vec3::new(0.0, 0.0, 0.0), |gradient, ball| {
+                let offset = point.sub(&ball.position);
+                let radius_squared = ball.radius * ball.radius;
+                let derivative = self.falloff.evaluate(offset.squared_magnitude() / radius_squared).1;
+
+                gradient.add(&offset.mul_by(ball.weight * derivative * 2.0 / radius_squared))
+            })
+    }
+
+    /// Ranges along the ray which lie inside of at least one ball, sorted and merged
+    fn get_intervals(&self, ray: &Ray) -> Vec<(f32, f32)> {
+        let mut intervals: Vec<(f32, f32)> = self.balls
+            .iter()
+            .filter_map(|ball| {
+                let origin = ray.position.sub(&ball.position);
+                let a = ray.direction.dot(&ray.direction);
+                let b = 2.0 * origin.dot(&ray.direction);
+                let c = origin.dot(&origin) - ball.radius * ball.radius;
+
+                let discriminant = b * b - 4.0 * a * c;
+                if discriminant < 0.0 { return None; }
+
+                let root = discriminant.sqrt();
+                let exit = (-b + root) / (2.0 * a);
+                if exit <= 0.0 { return None; }
+
+                Some((((-b - root) / (2.0 * a)).max(0.0), exit))
+            })
+            .collect();
+
+        intervals.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut merged: Vec<(f32, f32)> = Vec::with_capacity(intervals.len());
+        for (start, end) in intervals {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end))
+            }
+        }
+
+        merged
+    }
+}
+
+impl Shape for Metaballs {
+    fn has_radius(&self) -> bool { false }
+    fn is_3d(&self) -> bool { true }
+    fn get_surface_color(&self) -> Color { self.material.albedo }
+    fn get_position(&self) -> Vec3 { self.get_bounds().get_centroid() }
+    fn get_radius(&self) -> Option<f32> { None }
+    fn get_material(&self) -> Material { self.material.clone() }
+
+    fn get_vertices(&self) -> &[Vertex] {
+        &[]
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<Hit> {
+        let smallest_radius = self.balls.iter().map(|ball| ball.radius).fold(f32::INFINITY, f32::min);
+        let step = smallest_radius / STEPS_PER_RADIUS / ray.direction.magnitude();
+        let value_at = |distance: f32| self.get_field(&ray.get_point(distance)) - self.threshold;
+
+        for (start, end) in self.get_intervals(ray) {
+            let mut previous_distance = start;
+            let mut previous_value = value_at(start);
+
+            while previous_distance < end {
+                let distance = (previous_distance + step).min(end);
+                let value = value_at(distance);
+
+                // The field crossed the threshold, narrow down where
+                if (value > 0.0) != (previous_value > 0.0) {
+                    let (mut low, mut high) = (previous_distance, distance);
+
+                    for _ in 0..REFINE_STEPS {
+                        let middle = (low + high) / 2.0;
+                        if (value_at(middle) > 0.0) == (previous_value > 0.0) { low = middle; } else { high = middle; }
+                    }
+
+                    let distance = (low + high) / 2.0;
+                    if distance <= 0.0 { break; }
+
+                    let position = ray.get_point(distance);
+
+                    // The field decreases going out of the surface
+                    let normal = self.get_gradient(&position).invert().normalize();
+                    return Some(Hit::new(distance, position, normal));
+                }
+
+                previous_distance = distance;
+                previous_value = value;
+            }
+        }
+
+        None
+    }
+
+    fn get_bounds(&self) -> Aabb {
+        self.balls.iter().fold(Aabb::empty(), |bounds, ball| {
+            bounds.union(&Aabb::new(ball.position.sub_by(ball.radius), ball.position.add_by(ball.radius)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_ball() {
+        // (1 - r²)³ = 0.125 at r² = 0.5
+        let blob = Metaballs::new(0.125, Material::default())
+            .set_falloff(Falloff::Polynomial)
+            .add_ball(Vec3::new(0.0, 0.0, -5.0), 2.0, 1.0);
+
+        let hit = blob.intersect(&Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0))).unwrap();
+        assert!((hit.distance - (5.0 - 2.0f32.sqrt())).abs() < 1e-3);
+        assert!(hit.normal.sub(&Vec3::new(0.0, 0.0, 1.0)).magnitude() < 1e-3);
+
+        assert!(blob.intersect(&Ray::new(Vec3::new(1.5, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0))).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "positive radius")]
+    fn rejects_empty_balls() {
+        Metaballs::new(0.5, Material::default()).add_ball(Vec3::new(0.0, 0.0, 0.0), 0.0, 1.0);
+    }
+
+    #[test]
+    fn balls_blend() {
+        let blob = Metaballs::new(0.5, Material::default())
+            .add_ball(Vec3::new(-0.6, 0.0, 0.0), 1.0, 1.0)
+            .add_ball(Vec3::new(0.6, 0.0, 0.0), 1.0, 1.0);
+
+        // Neither ball reaches the threshold in the middle alone, together they do
+        let middle = Vec3::new(0.0, 0.0, 0.0);
+        let alone = Metaballs::new(0.5, Material::default()).add_ball(Vec3::new(-0.6, 0.0, 0.0), 1.0, 1.0);
+        assert!(alone.get_field(&middle) < 0.5);
+        assert!(blob.get_field(&middle) > 0.5);
+        assert!(blob.intersect(&Ray::new(Vec3::new(0.0, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0))).is_some());
+
+        let hit = blob.intersect(&Ray::new(Vec3::new(3.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0))).unwrap();
+        assert!(hit.normal.sub(&Vec3::new(1.0, 0.0, 0.0)).magnitude() < 1e-3);
+    }
+}
\ No newline at end of file
@@ -1,11 +1,33 @@
 mod circle;
 mod sphere;
+mod hit;
+mod surface_sample;
+mod transformed;
+mod mesh;
+mod subdivision;
+mod heightfield;
+mod curve;
+mod metaballs;
+mod voxel_grid;
+mod point_cloud;
 
 pub use circle::Circle;
 pub use sphere::Sphere;
+pub use hit::Hit;
+pub use surface_sample::SurfaceSample;
+pub use transformed::Transformed;
+pub use mesh::{Mesh, intersect_triangle};
+pub use subdivision::SubdivisionSurface;
+pub use heightfield::Heightfield;
+pub use curve::{Curve, CurveType, Curves};
+pub use metaballs::{Metaballs, Metaball, Falloff};
+pub use voxel_grid::VoxelGrid;
+pub use point_cloud::{PointCloud, Splat};
+
+use std::sync::Arc;
 
 use crate::{
-    util::{Color, Material, vec::Vec3},
+    util::{Aabb, Color, Material, Ray, vec::Vec3},
     renderer::Vertex
 };
 
@@ -17,4 +39,28 @@ pub trait Shape: 'static  {
     fn get_surface_color(&self) -> Color;
     fn get_material(&self) -> Material;
     fn get_vertices(&self) -> &[Vertex];
+    /// Returns the closest intersection in front of the ray, if any
+    fn intersect(&self, ray: &Ray) -> Option<Hit>;
+    /// Box the shape fits in, used to skip it quickly when rays miss
+    fn get_bounds(&self) -> Aabb;
+    /// Picks a random point on the surface so emissive shapes can light others directly,
+    /// `None` for shapes that don't support it
+    fn sample_surface(&self) -> Option<SurfaceSample> { None }
+    /// Material where the ray hit, for shapes whose material differs across them
+    fn get_hit_material(&self, _hit: &Hit) -> Material { self.get_material() }
+}
+
+/// Lets a single shape be shared by several [Transformed] instances
+impl<S: Shape> Shape for Arc<S> {
+    fn has_radius(&self) -> bool { self.as_ref().has_radius() }
+    fn is_3d(&self) -> bool { self.as_ref().is_3d() }
+    fn get_radius(&self) -> Option<f32> { self.as_ref().get_radius() }
+    fn get_position(&self) -> Vec3 { self.as_ref().get_position() }
+    fn get_surface_color(&self) -> Color { self.as_ref().get_surface_color() }
+    fn get_material(&self) -> Material { self.as_ref().get_material() }
+    fn get_vertices(&self) -> &[Vertex] { self.as_ref().get_vertices() }
+    fn intersect(&self, ray: &Ray) -> Option<Hit> { self.as_ref().intersect(ray) }
+    fn get_bounds(&self) -> Aabb { self.as_ref().get_bounds() }
+    fn sample_surface(&self) -> Option<SurfaceSample> { self.as_ref().sample_surface() }
+    fn get_hit_material(&self, hit: &Hit) -> Material { self.as_ref().get_hit_material(hit) }
 }
\ No newline at end of file
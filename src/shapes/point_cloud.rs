@@ -0,0 +1,179 @@
+use super::{Shape, Hit};
+use crate::{
+    environment::bvh::Bvh,
+    util::{
+        Aabb,
+        Material,
+        Color,
+        Ray,
+        vec::*
+    },
+    renderer::Vertex
+};
+
+/// Points per leaf of the acceleration structure, keeps it small for huge clouds
+const LEAF_SIZE: usize = 8;
+
+/// What every point of a [PointCloud] is drawn as
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Splat {
+    /// Flat disk facing along the point's normal
+    Disk,
+    /// Ball around the point, ignoring its normal
+    Sphere
+}
+
+/// Scanned points drawn as small splats, each colored by its own color
+pub struct PointCloud {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub colors: Vec<Color>,
+    /// Radius of every splat
+    pub radius: f32,
+    pub splat: Splat,
+    /// Used for everything but the albedo, which comes from `colors`
+    pub material: Material,
+    bvh: Bvh
+}
+
+impl PointCloud {
+    /// `positions`, `normals` and `colors` describe one point per index and need to be the same length
+    pub fn new(positions: Vec<Vec3>, normals: Vec<Vec3>, colors: Vec<Color>, radius: f32, material: Material) -> Self {
+        assert_eq!(positions.len(), normals.len(), "Every point needs a normal");
+        assert_eq!(positions.len(), colors.len(), "Every point needs a color");
+
+        let mut cloud = Self {
+            positions,
+            normals,
+            colors,
+            radius,
+            splat: Splat::Disk,
+            material,
+            bvh: Bvh::default()
+        };
+
+        cloud.update_bvh();
+        cloud
+    }
+
+    pub fn set_splat(mut self, splat: Splat) -> Self {
+        self.splat = splat;
+        self
+    }
+
+    /// Rebuilds the acceleration structure, call after editing `positions` or `radius`
+    pub fn update_bvh(&mut self) {
+        let bounds: Vec<Aabb> = self.positions
+            .iter()
+            .map(|position| Aabb::new(position.sub_by(self.radius), position.add_by(self.radius)))
+            .collect();
+
+        self.bvh = Bvh::with_leaf_size(&bounds, LEAF_SIZE);
+    }
+
+    fn intersect_point(&self, ray: &Ray, index: usize) -> Option<Hit> {
+        let position = &self.positions[index];
+
+        let (distance, normal) = match self.splat {
+            Splat::Disk => {
+                let normal = self.normals[index];
+                let facing = ray.direction.dot(&normal);
+                if facing.abs() < 1e-8 { return None; }
+
+                let distance = position.sub(&ray.position).dot(&normal) / facing;
+                if distance <= 0.0 { return None; }
+                if ray.get_point(distance).sub(position).squared_magnitude() > self.radius * self.radius { return None; }
+
+                // Disks are two sided, scanned normals don't always point the right way
+                (distance, if facing > 0.0 { normal.invert() } else { normal })
+            },
+            Splat::Sphere => {
+                let origin = ray.position.sub(position);
+                let a = ray.direction.dot(&ray.direction);
+                let b = 2.0 * origin.dot(&ray.direction);
+                let c = origin.dot(&origin) - self.radius * self.radius;
+
+                let discriminant = b * b - 4.0 * a * c;
+                if discriminant < 0.0 { return None; }
+
+                let distance = (-b - discriminant.sqrt()) / (2.0 * a);
+                if distance <= 0.0 { return None; }
+
+                (distance, ray.get_point(distance).sub(position).div_by(self.radius))
+            }
+        };
+
+        Some(Hit::new(distance, ray.get_point(distance), normal).set_material_index(index))
+    }
+}
+
+impl Shape for PointCloud {
+    fn has_radius(&self) -> bool { false }
+    fn is_3d(&self) -> bool { true }
+    fn get_surface_color(&self) -> Color { self.material.albedo }
+    fn get_position(&self) -> Vec3 { self.bvh.get_bounds().get_centroid() }
+    fn get_radius(&self) -> Option<f32> { None }
+    fn get_material(&self) -> Material { self.material.clone() }
+
+    /// The shared material, colored by the point that was hit
+    fn get_hit_material(&self, hit: &Hit) -> Material {
+        match hit.material_index {
+            Some(index) => Material { albedo: self.colors[index], ..self.material.clone() },
+            None => self.get_material()
+        }
+    }
+
+    fn get_vertices(&self) -> &[Vertex] {
+        &[]
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<Hit> {
+        self.bvh
+            .intersect(ray, |i| self.intersect_point(ray, i))
+            .map(|(_, hit)| hit)
+    }
+
+    fn get_bounds(&self) -> Aabb {
+        self.bvh.get_bounds()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cloud() -> PointCloud {
+        let positions = (0..1000)
+            .map(|i| Vec3::new((i % 10) as f32, ((i / 10) % 10) as f32, -((i / 100) as f32)))
+            .collect();
+        let colors = (0..1000).map(|i| Color::rgb(i as f32 / 1000.0, 0.0, 0.0)).collect();
+
+        PointCloud::new(positions, vec![Vec3::new(0.0, 0.0, 1.0); 1000], colors, 0.3, Material::default())
+    }
+
+    #[test]
+    fn disks_take_their_point_color() {
+        let cloud = cloud();
+        let hit = cloud.intersect(&Ray::new(Vec3::new(3.1, 4.2, 5.0), Vec3::new(0.0, 0.0, -1.0))).unwrap();
+
+        assert!((hit.distance - 5.0).abs() < 1e-4);
+        assert_eq!(cloud.get_hit_material(&hit).albedo, Color::rgb(0.043, 0.0, 0.0));
+        assert!(hit.normal.sub(&Vec3::new(0.0, 0.0, 1.0)).magnitude() < 1e-4);
+
+        // Gaps between the disks
+        assert!(cloud.intersect(&Ray::new(Vec3::new(3.5, 4.5, 5.0), Vec3::new(0.0, 0.0, -1.0))).is_none());
+
+        // Seen from behind the normal is flipped towards the ray
+        let hit = cloud.intersect(&Ray::new(Vec3::new(3.0, 4.0, -20.0), Vec3::new(0.0, 0.0, 1.0))).unwrap();
+        assert!(hit.normal.sub(&Vec3::new(0.0, 0.0, -1.0)).magnitude() < 1e-4);
+    }
+
+    #[test]
+    fn spheres() {
+        let cloud = cloud().set_splat(Splat::Sphere);
+        let hit = cloud.intersect(&Ray::new(Vec3::new(20.0, 2.0, -3.0), Vec3::new(-1.0, 0.0, 0.0))).unwrap();
+
+        assert!((hit.distance - 10.7).abs() < 1e-4);
+        assert_eq!(cloud.get_hit_material(&hit).albedo, Color::rgb(0.329, 0.0, 0.0));
+    }
+}
\ No newline at end of file
@@ -1,8 +1,13 @@
-use super::Shape;
+use std::f32::consts::PI;
+
+use super::{Shape, Hit, SurfaceSample};
 use crate::{
     util::{
+        Aabb,
         Material,
         Color,
+        Ray,
+        random,
         vec::*
     },
     renderer::Vertex
@@ -31,9 +36,76 @@ impl Shape for Sphere {
     fn get_surface_color(&self) -> Color { self.material.albedo }
     fn get_position(&self) -> Vec3 { self.position }
     fn get_radius(&self) -> Option<f32> { Some(self.radius) }
-    fn get_material(&self) -> Material { self.material }
+    fn get_material(&self) -> Material { self.material.clone() }
 
     fn get_vertices(&self) -> &[Vertex] {
         &[]
     }
+
+    fn intersect(&self, ray: &Ray) -> Option<Hit> {
+        let origin = ray.position.sub(&self.position);
+
+        let a = ray.direction.dot(&ray.direction);
+        let b = 2.0 * origin.dot(&ray.direction);
+        let c = origin.dot(&origin) - self.radius.powi(2);
+
+        // Determine the amount of intersactions between the ray
+        // and the radius of the circle
+        // < 0 means there is no collision
+        // = 0 means there is one collision
+        // > 0 means there are two collisions
+        // Quadratic formula discriminant:
+        // b^2 -4ac
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 { return None; }
+
+        // Rays starting inside the sphere hit it on the way out
+        let root = discriminant.sqrt();
+        let near = (-b - root) / (2.0 * a);
+        let distance = if near > 0.0 { near } else { (-b + root) / (2.0 * a) };
+        if distance <= 0.0 { return None; }
+
+        let position = ray.get_point(distance);
+        let normal = position.sub(&self.position).div_by(self.radius);
+
+        // Longitude around the vertical axis and latitude down from the north pole
+        let uv = Vec2::new(
+            0.5 + normal.z.atan2(normal.x) / (2.0 * PI),
+            normal.y.clamp(-1.0, 1.0).acos() / PI
+        );
+
+        // Analytic derivatives of the mapping above, the poles fall back to any frame
+        let ring = (normal.x * normal.x + normal.z * normal.z).sqrt();
+        let (dpdu, dpdv) = if ring > 1e-6 {
+            (
+                Vec3::new(-normal.z, 0.0, normal.x).mul_by(2.0 * PI * self.radius),
+                Vec3::new(normal.y * normal.x / ring, -ring, normal.y * normal.z / ring).mul_by(PI * self.radius)
+            )
+        } else {
+            (Vec3::new(2.0 * PI * self.radius, 0.0, 0.0), Vec3::new(0.0, 0.0, PI * self.radius))
+        };
+
+        Some(Hit::new(distance, position, normal).set_uv(uv).set_tangents(dpdu, dpdv))
+    }
+
+    fn get_bounds(&self) -> Aabb {
+        let radius = self.radius.abs();
+        Aabb::new(self.position.sub_by(radius), self.position.add_by(radius))
+    }
+
+    fn sample_surface(&self) -> Option<SurfaceSample> {
+        let radius = self.radius.abs();
+
+        // Uniform over the sphere: heights are evenly spread by Archimedes' hat-box theorem
+        let z = 1.0 - 2.0 * random();
+        let ring = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * random();
+        let normal = Vec3::new(ring * phi.cos(), ring * phi.sin(), z);
+
+        Some(SurfaceSample {
+            position: self.position.add(&normal.mul_by(radius)),
+            normal,
+            pdf: 1.0 / (4.0 * PI * radius * radius)
+        })
+    }
 }
\ No newline at end of file
@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+
+use super::Mesh;
+use crate::util::{Material, vec::*};
+
+/// Control cage rendered as a smooth Catmull-Clark subdivision surface.
+/// Call [SubdivisionSurface::tessellate] to turn it into a [Mesh] that can be added to a scene
+#[derive(Debug, Clone)]
+pub struct SubdivisionSurface {
+    pub positions: Vec<Vec3>,
+    /// Polygons of any size as indices into `positions`, counter-clockwise seen from the front
+    pub faces: Vec<Vec<usize>>,
+    /// Edges kept sharp, with how many levels they stay sharp for.
+    /// `f32::INFINITY` keeps an edge sharp on every level
+    pub creases: HashMap<(usize, usize), f32>,
+    pub level: u32,
+    pub material: Material
+}
+
+/// Faces around an edge and how sharp it is
+struct Edge {
+    faces: Vec<usize>,
+    sharpness: f32
+}
+
+impl Edge {
+    /// Boundary edges only have one face and are always treated as sharp
+    fn get_sharpness(&self) -> f32 {
+        if self.faces.len() < 2 { f32::INFINITY } else { self.sharpness }
+    }
+}
+
+impl SubdivisionSurface {
+    pub fn new(positions: Vec<Vec3>, faces: Vec<Vec<usize>>, material: Material) -> Self {
+        Self {
+            positions,
+            faces,
+            creases: HashMap::new(),
+            level: 2,
+            material
+        }
+    }
+
+    /// Set how many times the cage is subdivided before being tessellated
+    pub fn set_level(mut self, level: u32) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Mark the edge between the vertices `a` and `b` as a crease
+    pub fn add_crease(mut self, a: usize, b: usize, sharpness: f32) -> Self {
+        self.creases.insert(edge_key(a, b), sharpness);
+        self
+    }
+
+    /// Subdivide the cage `level` times and split the result into triangles
+    pub fn tessellate(&self) -> Mesh {
+        let mut surface = self.clone();
+
+        for _ in 0..self.level {
+            surface = surface.subdivide();
+        }
+
+        let triangles = surface.faces
+            .iter()
+            .flat_map(|face| (1..face.len() - 1).map(move |i| [face[0], face[i], face[i + 1]]))
+            .collect();
+
+        Mesh::new(surface.positions, triangles, self.material.clone())
+    }
+
+    /// Run a single Catmull-Clark step, every face is replaced by one quad per corner
+    pub fn subdivide(&self) -> Self {
+        let positions = &self.positions;
+        let edges = self.get_edges();
+
+        // Average of the face corners
+        let face_points: Vec<Vec3> = self.faces
+            .iter()
+            .map(|face| average(face.iter().map(|&i| positions[i])))
+            .collect();
+
+        // Midpoint of sharp edges, average of the edge ends and surrounding face points on smooth ones
+        let mut edge_indices: HashMap<(usize, usize), usize> = HashMap::with_capacity(edges.len());
+        let mut edge_points: Vec<Vec3> = Vec::with_capacity(edges.len());
+
+        for (&(a, b), edge) in &edges {
+            let midpoint = positions[a].add(&positions[b]).mul_by(0.5);
+            let sharpness = edge.get_sharpness();
+
+            let point = if sharpness >= 1.0 {
+                midpoint
+            } else {
+                let smooth = average(
+                    [positions[a], positions[b]]
+                        .into_iter()
+                        .chain(edge.faces.iter().map(|&f| face_points[f]))
+                );
+                lerp(&smooth, &midpoint, sharpness)
+            };
+
+            edge_indices.insert((a, b), edge_points.len());
+            edge_points.push(point);
+        }
+
+        let vertex_points = self.get_vertex_points(&edges, &face_points);
+
+        // New vertices are laid out as: vertex points, edge points, face points
+        let edge_offset = vertex_points.len();
+        let face_offset = edge_offset + edge_points.len();
+        let edge_index = |a: usize, b: usize| edge_offset + edge_indices[&edge_key(a, b)];
+
+        let mut faces = Vec::with_capacity(self.faces.len() * 4);
+
+        for (f, face) in self.faces.iter().enumerate() {
+            for i in 0..face.len() {
+                let previous = face[(i + face.len() - 1) % face.len()];
+                let current = face[i];
+                let next = face[(i + 1) % face.len()];
+
+                faces.push(vec![
+                    current,
+                    edge_index(current, next),
+                    face_offset + f,
+                    edge_index(previous, current)
+                ]);
+            }
+        }
+
+        // Both halves of a crease stay sharp for one level less
+        let mut creases = HashMap::new();
+
+        for (&(a, b), &sharpness) in &self.creases {
+            if sharpness <= 1.0 || !edges.contains_key(&(a, b)) { continue; }
+
+            let middle = edge_index(a, b);
+            creases.insert(edge_key(a, middle), sharpness - 1.0);
+            creases.insert(edge_key(middle, b), sharpness - 1.0);
+        }
+
+        Self {
+            positions: vertex_points
+                .into_iter()
+                .chain(edge_points)
+                .chain(face_points)
+                .collect(),
+            faces,
+            creases,
+            level: self.level,
+            material: self.material.clone()
+        }
+    }
+
+    fn get_edges(&self) -> HashMap<(usize, usize), Edge> {
+        let mut edges: HashMap<(usize, usize), Edge> = HashMap::new();
+
+        for (f, face) in self.faces.iter().enumerate() {
+            for i in 0..face.len() {
+                let key = edge_key(face[i], face[(i + 1) % face.len()]);
+
+                edges
+                    .entry(key)
+                    .or_insert_with(|| Edge {
+                        faces: Vec::with_capacity(2),
+                        sharpness: self.creases.get(&key).copied().unwrap_or(0.0)
+                    })
+                    .faces
+                    .push(f);
+            }
+        }
+
+        edges
+    }
+
+    /// New positions for the original vertices
+    fn get_vertex_points(&self, edges: &HashMap<(usize, usize), Edge>, face_points: &[Vec3]) -> Vec<Vec3> {
+        let positions = &self.positions;
+        let mut neighbours: Vec<Vec<(usize, f32)>> = vec![Vec::new(); positions.len()];
+        let mut faces: Vec<Vec<usize>> = vec![Vec::new(); positions.len()];
+
+        for (&(a, b), edge) in edges {
+            neighbours[a].push((b, edge.get_sharpness()));
+            neighbours[b].push((a, edge.get_sharpness()));
+        }
+
+        for (f, face) in self.faces.iter().enumerate() {
+            for &i in face {
+                faces[i].push(f);
+            }
+        }
+
+        positions
+            .iter()
+            .enumerate()
+            .map(|(i, position)| {
+                // Vertices which aren't part of any face are left alone
+                if neighbours[i].is_empty() { return *position; }
+
+                let sharp: Vec<&(usize, f32)> = neighbours[i]
+                    .iter()
+                    .filter(|(_, sharpness)| *sharpness > 0.0)
+                    .collect();
+
+                // Smooth rule: (F + 2R + (n - 3)P) / n
+                let valence = neighbours[i].len() as f32;
+                let face_average = average(faces[i].iter().map(|&f| face_points[f]));
+                let edge_average = average(
+                    neighbours[i].iter().map(|(n, _)| position.add(&positions[*n]).mul_by(0.5))
+                );
+
+                let smooth = face_average
+                    .add(&edge_average.mul_by(2.0))
+                    .add(&position.mul_by(valence - 3.0))
+                    .div_by(valence);
+
+                // Fewer than two creases (a dart) don't change the vertex rule
+                if sharp.len() < 2 { return smooth; }
+
+                // Boundary vertices with no other edges are corners as well
+                let sharp_rule = if sharp.len() == 2 && neighbours[i].len() > 2 {
+                    // Crease rule: slide along the crease, (a + 6P + b) / 8
+                    positions[sharp[0].0]
+                        .add(&positions[sharp[1].0])
+                        .add(&position.mul_by(6.0))
+                        .div_by(8.0)
+                } else {
+                    // Corners stay where they are
+                    *position
+                };
+
+                let sharpness = sharp.iter().map(|(_, s)| s.min(1.0)).sum::<f32>() / sharp.len() as f32;
+                lerp(&smooth, &sharp_rule, sharpness)
+            })
+            .collect()
+    }
+}
+
+fn edge_key(a: usize, b: usize) -> (usize, usize) {
+    (a.min(b), a.max(b))
+}
+
+fn average(points: impl Iterator<Item = Vec3>) -> Vec3 {
+    let (sum, count) = points.fold((Vec3::new(0.0, 0.0, 0.0), 0), |(sum, count), point| {
+        (sum.add(&point), count + 1)
+    });
+
+    if count == 0 { return sum; }
+    sum.div_by(count as f32)
+}
+
+fn lerp(a: &Vec3, b: &Vec3, t: f32) -> Vec3 {
+    a.mul_by(1.0 - t).add(&b.mul_by(t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{shapes::Shape, util::Ray};
+
+    fn cube() -> SubdivisionSurface {
+        let positions = (0..8)
+            .map(|i| Vec3::new(
+                if i & 1 == 0 { -1.0 } else { 1.0 },
+                if i & 2 == 0 { -1.0 } else { 1.0 },
+                if i & 4 == 0 { -1.0 } else { 1.0 }
+            ))
+            .collect();
+
+        let faces = vec![
+            vec![0, 2, 3, 1], vec![4, 5, 7, 6],
+            vec![0, 1, 5, 4], vec![2, 6, 7, 3],
+            vec![0, 4, 6, 2], vec![1, 3, 7, 5]
+        ];
+
+        SubdivisionSurface::new(positions, faces, Material::default())
+    }
+
+    fn assert_vec3_close(a: Vec3, b: Vec3) {
+        assert!(a.sub(&b).magnitude() < 1e-4, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn smooth_cube() {
+        let subdivided = cube().subdivide();
+
+        assert_eq!(subdivided.faces.len(), 24);
+        assert_eq!(subdivided.positions.len(), 8 + 12 + 6);
+        assert_vec3_close(subdivided.positions[7], Vec3::new(5.0 / 9.0, 5.0 / 9.0, 5.0 / 9.0));
+    }
+
+    #[test]
+    fn creased_corners_stay_put() {
+        let mut surface = cube();
+        for (a, b) in [(0, 1), (0, 2), (0, 4)] {
+            surface = surface.add_crease(a, b, f32::INFINITY);
+        }
+
+        let subdivided = surface.subdivide();
+        assert_vec3_close(subdivided.positions[0], Vec3::new(-1.0, -1.0, -1.0));
+        assert_eq!(subdivided.creases.len(), 6);
+
+        // A crease running out after a level is smooth from then on
+        let subdivided = cube().add_crease(0, 1, 1.0).subdivide();
+        assert!(subdivided.creases.is_empty());
+    }
+
+    #[test]
+    fn boundary_is_kept() {
+        let quad = SubdivisionSurface::new(
+            vec![
+                Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(1.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0)
+            ],
+            vec![vec![0, 1, 2, 3]],
+            Material::default()
+        );
+
+        let subdivided = quad.subdivide();
+        assert_vec3_close(subdivided.positions[2], Vec3::new(1.0, 1.0, 0.0));
+        assert!(subdivided.positions.iter().all(|p| p.z == 0.0));
+    }
+
+    #[test]
+    fn tessellated_mesh_is_hit() {
+        let mesh = cube().set_level(3).tessellate();
+        assert_eq!(mesh.triangles.len(), 6 * 4usize.pow(3) * 2);
+
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = mesh.intersect(&ray).unwrap();
+
+        // The limit surface of a cube sits well inside of its cage
+        assert!(hit.distance > 4.0 && hit.distance < 4.5);
+        assert_vec3_close(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+    }
+}
\ No newline at end of file
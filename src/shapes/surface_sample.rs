@@ -0,0 +1,11 @@
+use crate::util::vec::Vec3;
+
+/// Point picked on the surface of a [Shape](super::Shape), used to aim rays at emitters
+#[derive(Debug, Clone, Copy)]
+pub struct SurfaceSample {
+    pub position: Vec3,
+    /// Unit surface normal facing away from the shape
+    pub normal: Vec3,
+    /// Probability density of picking the point, per unit of area
+    pub pdf: f32
+}
\ No newline at end of file
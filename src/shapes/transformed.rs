@@ -0,0 +1,214 @@
+use super::{Shape, Hit, SurfaceSample};
+use crate::{
+    util::{
+        Aabb,
+        Material,
+        Color,
+        Ray,
+        matrix::Matrix4,
+        vec::*
+    },
+    renderer::Vertex
+};
+
+/// Places a [Shape] in the scene through an object-to-world transform.
+/// Wrap the shape in an `Arc` to instance it several times without copying it
+pub struct Transformed<S: Shape> {
+    pub shape: S,
+    transform: Matrix4,
+    inverse: Matrix4
+}
+
+impl<S: Shape> Transformed<S> {
+    /// Panics if `transform` cannot be inverted (e.g. a scale of zero)
+    pub fn new(shape: S, transform: Matrix4) -> Self {
+        let inverse = transform
+            .inverse()
+            .expect("Shape transforms must be invertible");
+
+        Self { shape, transform, inverse }
+    }
+
+    pub fn get_transform(&self) -> &Matrix4 {
+        &self.transform
+    }
+
+    pub fn get_inverse(&self) -> &Matrix4 {
+        &self.inverse
+    }
+
+    /// Replaces the object-to-world transform
+    pub fn set_transform(mut self, transform: Matrix4) -> Self {
+        self.inverse = transform
+            .inverse()
+            .expect("Shape transforms must be invertible");
+        self.transform = transform;
+        self
+    }
+
+    /// Moves the shape by `offset` after the current transform
+    pub fn translate(self, offset: Vec3) -> Self {
+        let transform = Matrix4::translation(offset).mul(&self.transform);
+        self.set_transform(transform)
+    }
+
+    /// Rotates the shape `angle` radians around `axis` after the current transform
+    pub fn rotate(self, axis: Vec3, angle: f32) -> Self {
+        let transform = Matrix4::rotation(axis, angle).mul(&self.transform);
+        self.set_transform(transform)
+    }
+
+    /// Scales the shape per axis after the current transform
+    pub fn scale(self, factor: Vec3) -> Self {
+        let transform = Matrix4::scaling(factor).mul(&self.transform);
+        self.set_transform(transform)
+    }
+}
+
+impl<S: Shape> Shape for Transformed<S> {
+    fn is_3d(&self) -> bool { self.shape.is_3d() }
+    fn get_surface_color(&self) -> Color { self.shape.get_surface_color() }
+    fn get_position(&self) -> Vec3 { self.transform.transform_point(&self.shape.get_position()) }
+    fn get_material(&self) -> Material { self.shape.get_material() }
+    fn get_hit_material(&self, hit: &Hit) -> Material { self.shape.get_hit_material(hit) }
+
+    // A radius stops being meaningful once the shape can be scaled unevenly
+    fn has_radius(&self) -> bool { false }
+    fn get_radius(&self) -> Option<f32> { None }
+
+    fn get_vertices(&self) -> &[Vertex] {
+        self.shape.get_vertices()
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<Hit> {
+        // The direction is left unnormalized so that distances
+        // along the ray are the same in object and world space
+        let local_ray = Ray::new(
+            self.inverse.transform_point(&ray.position),
+            self.inverse.transform_vector(&ray.direction)
+        );
+
+        let hit = self.shape.intersect(&local_ray)?;
+
+        // Normals are transformed by the inverse transpose so they stay
+        // perpendicular to the surface under non-uniform scaling
+        let normal = self.inverse
+            .transpose()
+            .transform_vector(&hit.normal)
+            .normalize();
+
+        Some(Hit {
+            position: self.transform.transform_point(&hit.position),
+            normal,
+            dpdu: self.transform.transform_vector(&hit.dpdu),
+            dpdv: self.transform.transform_vector(&hit.dpdv),
+            ..hit
+        })
+    }
+
+    fn get_bounds(&self) -> Aabb {
+        self.shape.get_bounds().transform(&self.transform)
+    }
+
+    fn sample_surface(&self) -> Option<SurfaceSample> {
+        let sample = self.shape.sample_surface()?;
+        let normal = self.inverse.transpose().transform_vector(&sample.normal);
+
+        // How much the transform stretches areas around the point
+        let area_scale = self.transform.to_matrix3().determinant().abs() * normal.magnitude();
+
+        Some(SurfaceSample {
+            position: self.transform.transform_point(&sample.position),
+            normal: normal.normalize(),
+            pdf: sample.pdf / area_scale
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::{Mesh, Sphere};
+
+    fn assert_close(a: &Vec3, b: &Vec3) {
+        assert!(a.sub(b).magnitude() < 1e-4, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn intersects_translated_shapes() {
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, Material::default());
+        let moved = Transformed::new(sphere, Matrix4::translation(Vec3::new(0.0, 0.0, -5.0)));
+
+        // Distances are in units of the unnormalized world direction
+        let hit = moved.intersect(&Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -2.0))).unwrap();
+        assert!((hit.distance - 2.0).abs() < 1e-5);
+        assert_close(&hit.position, &Vec3::new(0.0, 0.0, -4.0));
+        assert_close(&hit.normal, &Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn intersects_rotated_shapes() {
+        let sphere = Sphere::new(Vec3::new(2.0, 0.0, 0.0), 1.0, Material::default());
+        let rotated = Transformed::new(sphere, Matrix4::rotation(Vec3::new(0.0, 1.0, 0.0), std::f32::consts::FRAC_PI_2));
+
+        // A quarter turn around y swings the sphere from the x axis onto the z axis
+        let center = rotated.get_transform().transform_point(&Vec3::new(2.0, 0.0, 0.0));
+        assert!(center.x.abs() < 1e-5 && (center.z.abs() - 2.0).abs() < 1e-5);
+
+        let hit = rotated.intersect(&Ray::new(center.add(&Vec3::new(0.0, 5.0, 0.0)), Vec3::new(0.0, -1.0, 0.0))).unwrap();
+        assert!((hit.distance - 4.0).abs() < 1e-4);
+        assert_close(&hit.position, &center.add(&Vec3::new(0.0, 1.0, 0.0)));
+        assert_close(&hit.normal, &Vec3::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn intersects_stretched_shapes() {
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, Material::default());
+        let ellipsoid = Transformed::new(sphere, Matrix4::scaling(Vec3::new(2.0, 1.0, 1.0)));
+
+        let hit = ellipsoid.intersect(&Ray::new(Vec3::new(5.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0))).unwrap();
+        assert!((hit.distance - 3.0).abs() < 1e-4);
+        assert_close(&hit.position, &Vec3::new(2.0, 0.0, 0.0));
+        assert_close(&hit.normal, &Vec3::new(1.0, 0.0, 0.0));
+
+        // Away from the axes the normal follows the inverse transpose, (x / 4, y, z) on this ellipsoid
+        let x = std::f32::consts::SQRT_2;
+        let hit = ellipsoid.intersect(&Ray::new(Vec3::new(x, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0))).unwrap();
+        assert!((hit.distance - (5.0 - x / 2.0)).abs() < 1e-4);
+        assert_close(&hit.position, &Vec3::new(x, x / 2.0, 0.0));
+        assert_close(&hit.normal, &Vec3::new(1.0, 2.0, 0.0).normalize());
+    }
+
+    #[test]
+    fn samples_scaled_surfaces() {
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, Material::default());
+        let scaled = Transformed::new(sphere, Matrix4::scaling(Vec3::new(2.0, 2.0, 2.0)))
+            .translate(Vec3::new(0.0, 5.0, 0.0));
+
+        for _ in 0..16 {
+            let sample = scaled.sample_surface().unwrap();
+            let offset = sample.position.sub(&Vec3::new(0.0, 5.0, 0.0));
+
+            assert!((offset.magnitude() - 2.0).abs() < 1e-4);
+            assert!(offset.normalize().sub(&sample.normal).magnitude() < 1e-4);
+            assert!((sample.pdf - 1.0 / (16.0 * std::f32::consts::PI)).abs() < 1e-6);
+        }
+
+        // Stretching a flat quad only along its plane scales its area evenly
+        let quad = Mesh::new(
+            vec![Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 1.0), Vec3::new(0.0, 0.0, 1.0)],
+            vec![[0, 2, 1], [0, 3, 2]],
+            Material::default()
+        );
+        let stretched = Transformed::new(quad, Matrix4::scaling(Vec3::new(3.0, 7.0, 1.0)));
+
+        for _ in 0..16 {
+            let sample = stretched.sample_surface().unwrap();
+
+            assert!(sample.position.y.abs() < 1e-6);
+            assert!(sample.position.x >= 0.0 && sample.position.x <= 3.0);
+            assert!(sample.position.z >= 0.0 && sample.position.z <= 1.0);
+            assert!((sample.pdf - 1.0 / 3.0).abs() < 1e-5);
+        }
+    }
+}
\ No newline at end of file
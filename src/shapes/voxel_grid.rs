@@ -0,0 +1,219 @@
+use super::{Shape, Hit};
+use crate::{
+    util::{
+        Aabb,
+        Material,
+        Color,
+        Ray,
+        vec::*
+    },
+    renderer::Vertex
+};
+
+/// Dense grid of solid cubes, each using a material from a shared palette
+#[derive(Debug, Clone)]
+pub struct VoxelGrid {
+    /// Lowest corner of the grid
+    pub position: Vec3,
+    pub voxel_size: f32,
+    /// Palette the voxels pick their material from, up to 255 entries
+    pub materials: Vec<Material>,
+    /// Amount of voxels along X, Y and Z
+    resolution: (usize, usize, usize),
+    /// 0 for empty voxels, otherwise the index into `materials` plus one
+    voxels: Vec<u8>
+}
+
+impl VoxelGrid {
+    /// Creates an empty grid
+    pub fn new(resolution: (usize, usize, usize), voxel_size: f32, materials: Vec<Material>) -> Self {
+        assert!(materials.len() <= u8::MAX as usize, "Voxel grids support up to 255 materials");
+        assert!(resolution.0 > 0 && resolution.1 > 0 && resolution.2 > 0, "Grids need at least one voxel per axis");
+
+        Self {
+            position: Vec3::new(0.0, 0.0, 0.0),
+            voxel_size,
+            materials,
+            resolution,
+            voxels: vec![0; resolution.0 * resolution.1 * resolution.2]
+        }
+    }
+
+    pub fn set_position(mut self, position: Vec3) -> Self {
+        self.position = position;
+        self
+    }
+
+    pub fn get_resolution(&self) -> (usize, usize, usize) {
+        self.resolution
+    }
+
+    fn get_index(&self, x: usize, y: usize, z: usize) -> usize {
+        x + (y + z * self.resolution.1) * self.resolution.0
+    }
+
+    /// Index into `materials` of the voxel, `None` when it is empty
+    pub fn get_voxel(&self, x: usize, y: usize, z: usize) -> Option<usize> {
+        match self.voxels[self.get_index(x, y, z)] {
+            0 => None,
+            value => Some(value as usize - 1)
+        }
+    }
+
+    /// Fill the voxel with the material at `material` in the palette, or empty it with `None`
+    pub fn set_voxel(&mut self, x: usize, y: usize, z: usize, material: Option<usize>) {
+        let value = match material {
+            Some(material) => {
+                assert!(material < self.materials.len(), "Voxel material is not in the palette");
+                material as u8 + 1
+            },
+            None => 0
+        };
+
+        let index = self.get_index(x, y, z);
+        self.voxels[index] = value;
+    }
+}
+
+impl Shape for VoxelGrid {
+    fn has_radius(&self) -> bool { false }
+    fn is_3d(&self) -> bool { true }
+    fn get_surface_color(&self) -> Color { self.get_material().albedo }
+    fn get_position(&self) -> Vec3 { self.position }
+    fn get_radius(&self) -> Option<f32> { None }
+    fn get_material(&self) -> Material { self.materials.first().cloned().unwrap_or_default() }
+
+    fn get_hit_material(&self, hit: &Hit) -> Material {
+        match hit.material_index {
+            Some(index) => self.materials[index].clone(),
+            None => self.get_material()
+        }
+    }
+
+    fn get_vertices(&self) -> &[Vertex] {
+        &[]
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<Hit> {
+        // Work in voxel units with the grid starting at 0, 0, 0
+        let origin = ray.position.sub(&self.position).div_by(self.voxel_size);
+        let direction = ray.direction.div_by(self.voxel_size);
+        let local_ray = Ray::new(origin, direction);
+
+        let resolution = [self.resolution.0, self.resolution.1, self.resolution.2];
+        let size = Vec3::new(resolution[0] as f32, resolution[1] as f32, resolution[2] as f32);
+        let inverse_direction = local_ray.get_inverse_direction();
+        let enter = Aabb::new(Vec3::new(0.0, 0.0, 0.0), size).intersect(&origin, &inverse_direction, f32::INFINITY)?;
+
+        let origin = [origin.x, origin.y, origin.z];
+        let direction = [direction.x, direction.y, direction.z];
+        let inverse = [inverse_direction.x, inverse_direction.y, inverse_direction.z];
+        let size = [size.x, size.y, size.z];
+
+        // The axis whose face the ray entered the grid through, if it started outside
+        let mut axis = (0..3)
+            .map(|i| (i, ((0.0 - origin[i]) * inverse[i]).min((size[i] - origin[i]) * inverse[i])))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+            .filter(|_| enter > 0.0);
+
+        let start = local_ray.get_point(enter);
+        let start = [start.x, start.y, start.z];
+
+        // Amanatides & Woo: step one voxel at a time along whichever axis reaches its next boundary first
+        let mut cell = [0usize; 3];
+        let mut step = [0isize; 3];
+        let mut next = [0.0f32; 3];
+        let mut delta = [0.0f32; 3];
+
+        for i in 0..3 {
+            cell[i] = (start[i].max(0.0) as usize).min(resolution[i] - 1);
+            step[i] = if direction[i] >= 0.0 { 1 } else { -1 };
+            delta[i] = inverse[i].abs();
+            next[i] = ((cell[i] as isize + step[i].max(0)) as f32 - origin[i]) * inverse[i];
+        }
+
+        let mut distance = enter;
+
+        loop {
+            // Voxels the ray starts inside of are skipped, so rays can leave solid areas
+            if let (Some(material), Some(axis)) = (self.get_voxel(cell[0], cell[1], cell[2]), axis) {
+                let mut normal = [0.0; 3];
+                normal[axis] = -step[axis] as f32;
+
+                return Some(
+                    Hit::new(distance, ray.get_point(distance), Vec3::new(normal[0], normal[1], normal[2]))
+                        .set_material_index(material)
+                );
+            }
+
+            let i = (0..3).min_by(|&a, &b| next[a].total_cmp(&next[b])).unwrap();
+            cell[i] = cell[i].checked_add_signed(step[i]).filter(|&c| c < resolution[i])?;
+
+            distance = next[i];
+            next[i] += delta[i];
+            axis = Some(i);
+        }
+    }
+
+    fn get_bounds(&self) -> Aabb {
+        let (x, y, z) = self.resolution;
+        let size = Vec3::new(x as f32, y as f32, z as f32).mul_by(self.voxel_size);
+
+        Aabb::new(self.position, self.position.add(&size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> VoxelGrid {
+        let materials = vec![
+            Material::new(Color::rgb(1.0, 0.0, 0.0), 1.0, 0.0),
+            Material::new(Color::rgb(0.0, 0.0, 1.0), 1.0, 0.0)
+        ];
+
+        let mut grid = VoxelGrid::new((4, 4, 4), 0.5, materials).set_position(Vec3::new(-1.0, -1.0, -1.0));
+        grid.set_voxel(1, 1, 1, Some(0));
+        grid.set_voxel(2, 1, 1, Some(1));
+        grid
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one voxel")]
+    fn rejects_empty_resolutions() {
+        VoxelGrid::new((4, 0, 4), 1.0, Vec::new());
+    }
+
+    #[test]
+    fn hits_voxel_faces() {
+        let grid = grid();
+
+        // Voxel 1, 1, 1 spans -0.5 to 0.0 on every axis
+        let hit = grid.intersect(&Ray::new(Vec3::new(-0.25, -0.25, 5.0), Vec3::new(0.0, 0.0, -1.0))).unwrap();
+        assert!((hit.distance - 5.0).abs() < 1e-4);
+        assert!(hit.normal.sub(&Vec3::new(0.0, 0.0, 1.0)).magnitude() < 1e-4);
+        assert_eq!(grid.get_hit_material(&hit).albedo, Color::rgb(1.0, 0.0, 0.0));
+
+        let hit = grid.intersect(&Ray::new(Vec3::new(3.0, -0.25, -0.25), Vec3::new(-2.0, 0.0, 0.0))).unwrap();
+        assert!((hit.distance - 1.25).abs() < 1e-4);
+        assert!(hit.normal.sub(&Vec3::new(1.0, 0.0, 0.0)).magnitude() < 1e-4);
+        assert_eq!(grid.get_hit_material(&hit).albedo, Color::rgb(0.0, 0.0, 1.0));
+
+        assert!(grid.intersect(&Ray::new(Vec3::new(0.75, 0.75, 5.0), Vec3::new(0.0, 0.0, -1.0))).is_none());
+    }
+
+    #[test]
+    fn diagonal_rays_step_between_voxels() {
+        let grid = grid();
+
+        // Enters the grid through the top, crossing empty voxels first
+        let hit = grid.intersect(&Ray::new(Vec3::new(0.8, 2.0, -0.25), Vec3::new(-0.4, -1.0, 0.0))).unwrap();
+        assert!(hit.normal.sub(&Vec3::new(0.0, 1.0, 0.0)).magnitude() < 1e-4);
+        assert!((hit.position.y - 0.0).abs() < 1e-4);
+
+        // Rays starting inside a voxel can get out of it
+        assert!(grid.intersect(&Ray::new(Vec3::new(-0.25, -0.25, -0.25), Vec3::new(0.0, 0.0, 1.0))).is_none());
+    }
+}
\ No newline at end of file
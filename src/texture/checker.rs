@@ -0,0 +1,62 @@
+use super::{Mapping, Texture};
+use crate::{
+    shapes::Hit,
+    util::Color
+};
+
+/// Alternating squares in UV space, or cubes in space
+#[derive(Debug, Clone, Copy)]
+pub struct Checker {
+    pub even: Color,
+    pub odd: Color,
+    /// Squares per unit of UV or space
+    pub scale: f32,
+    pub mapping: Mapping
+}
+
+impl Checker {
+    /// 2D checkerboard laid out over the UVs
+    pub fn new(even: Color, odd: Color, scale: f32) -> Self {
+        Self { even, odd, scale, mapping: Mapping::Uv }
+    }
+
+    /// 3D checkerboard of cubes filling space, independent of the UVs
+    pub fn solid(even: Color, odd: Color, scale: f32) -> Self {
+        Self { even, odd, scale, mapping: Mapping::Position }
+    }
+}
+
+impl Texture for Checker {
+    fn evaluate(&self, hit: &Hit) -> Color {
+        let point = self.mapping.get_point(hit);
+        let cell = (point.x * self.scale).floor() + (point.y * self.scale).floor() + (point.z * self.scale).floor();
+
+        if cell.rem_euclid(2.0) < 1.0 { self.even } else { self.odd }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::vec::{Vec2, Vec3};
+
+    fn hit_at(position: Vec3, uv: Vec2) -> Hit {
+        Hit::new(1.0, position, Vec3::new(0.0, 1.0, 0.0)).set_uv(uv)
+    }
+
+    #[test]
+    fn checker_alternates() {
+        let (black, white) = (Color::rgb(0.0, 0.0, 0.0), Color::rgb(1.0, 1.0, 1.0));
+        let origin = Vec3::new(0.0, 0.0, 0.0);
+
+        let flat = Checker::new(black, white, 2.0);
+        assert_eq!(flat.evaluate(&hit_at(origin, Vec2::new(0.25, 0.25))), black);
+        assert_eq!(flat.evaluate(&hit_at(origin, Vec2::new(0.75, 0.25))), white);
+        assert_eq!(flat.evaluate(&hit_at(origin, Vec2::new(0.75, 0.75))), black);
+
+        let solid = Checker::solid(black, white, 1.0);
+        assert_eq!(solid.evaluate(&hit_at(Vec3::new(0.5, 0.5, 0.5), Vec2::new(0.0, 0.0))), black);
+        assert_eq!(solid.evaluate(&hit_at(Vec3::new(0.5, 0.5, -0.5), Vec2::new(0.0, 0.0))), white);
+        assert_eq!(solid.evaluate(&hit_at(Vec3::new(-0.5, -0.5, 0.5), Vec2::new(0.0, 0.0))), black);
+    }
+}
\ No newline at end of file
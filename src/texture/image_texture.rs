@@ -0,0 +1,356 @@
+use std::{fmt, path::Path};
+
+use image::{DynamicImage, ImageResult};
+
+use super::Texture;
+use crate::{
+    shapes::Hit,
+    util::{Color, vec::*}
+};
+
+/// How the values stored in an image relate to light
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// Gamma encoded, as colors in most images are
+    Srgb,
+    /// Stored as is, for data such as roughness or metallic maps
+    Linear
+}
+
+/// What happens to UVs outside of 0.0 - 1.0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WrapMode {
+    Repeat,
+    Mirror,
+    Clamp
+}
+
+/// Most samples an anisotropic lookup takes along the footprint
+const MAX_ANISOTROPY: f32 = 8.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+    /// Blends the two mip levels closest to the size of the ray's footprint
+    Trilinear,
+    /// Several trilinear samples along footprints stretched by grazing angles
+    Anisotropic
+}
+
+/// One level of the mip pyramid, each half the size of the previous
+struct MipLevel {
+    width: u32,
+    height: u32,
+    /// Linear colors, row by row from the top
+    pixels: Vec<Color>
+}
+
+/// Texture read from an image file, UV (0, 0) being its top left corner
+pub struct ImageTexture {
+    /// Starting with the full image, down to a single pixel
+    levels: Vec<MipLevel>,
+    pub wrap: WrapMode,
+    pub filter: Filter
+}
+
+impl fmt::Debug for ImageTexture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (width, height) = self.get_dimensions();
+
+        f.debug_struct("ImageTexture")
+            .field("width", &width)
+            .field("height", &height)
+            .field("levels", &self.levels.len())
+            .field("wrap", &self.wrap)
+            .field("filter", &self.filter)
+            .finish()
+    }
+}
+
+impl ImageTexture {
+    /// Loads any image format supported by the `image` crate
+    pub fn open<P: AsRef<Path>>(path: P, color_space: ColorSpace) -> ImageResult<Self> {
+        Ok(Self::from_image(&image::open(path)?, color_space))
+    }
+
+    pub fn from_image(image: &DynamicImage, color_space: ColorSpace) -> Self {
+        let image = image.to_rgba32f();
+
+        let pixels = image
+            .pixels()
+            .map(|pixel| {
+                let [r, g, b, a] = pixel.0;
+
+                match color_space {
+                    ColorSpace::Srgb => Color::rgba(srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), a),
+                    ColorSpace::Linear => Color::rgba(r, g, b, a)
+                }
+            })
+            .collect();
+
+        Self::new(image.width(), image.height(), pixels)
+    }
+
+    /// Creates a texture from linear colors, row by row from the top
+    pub fn new(width: u32, height: u32, pixels: Vec<Color>) -> Self {
+        assert!(width > 0 && height > 0, "Textures need at least one pixel");
+        assert_eq!(pixels.len(), (width * height) as usize, "Wrong amount of pixels");
+
+        let mut levels = vec![MipLevel { width, height, pixels }];
+        while let Some(level) = levels.last().filter(|level| level.width > 1 || level.height > 1) {
+            levels.push(level.downsample());
+        }
+
+        Self {
+            levels,
+            wrap: WrapMode::Repeat,
+            filter: Filter::Trilinear
+        }
+    }
+
+    pub fn set_wrap(mut self, wrap: WrapMode) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn set_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn get_dimensions(&self) -> (u32, u32) {
+        (self.levels[0].width, self.levels[0].height)
+    }
+
+    pub fn get_level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Pixel of a mip level at integer coordinates, which may lie outside of the image
+    fn get_texel(&self, level: usize, x: i64, y: i64) -> Color {
+        let level = &self.levels[level];
+        let x = wrap(x, level.width as i64, self.wrap);
+        let y = wrap(y, level.height as i64, self.wrap);
+
+        level.pixels[y * level.width as usize + x]
+    }
+
+    fn sample_nearest(&self, level: usize, uv: &Vec2) -> Color {
+        let (width, height) = (self.levels[level].width as f32, self.levels[level].height as f32);
+        self.get_texel(level, (uv.x * width).floor() as i64, (uv.y * height).floor() as i64)
+    }
+
+    fn sample_bilinear(&self, level: usize, uv: &Vec2) -> Color {
+        let (width, height) = (self.levels[level].width as f32, self.levels[level].height as f32);
+
+        // Pixel centers sit halfway between integer coordinates
+        let (x, y) = (uv.x * width - 0.5, uv.y * height - 0.5);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = lerp(&self.get_texel(level, x0, y0), &self.get_texel(level, x0 + 1, y0), fx);
+        let bottom = lerp(&self.get_texel(level, x0, y0 + 1), &self.get_texel(level, x0 + 1, y0 + 1), fx);
+
+        lerp(&top, &bottom, fy)
+    }
+
+    /// Blends bilinear samples of the two levels around a fractional `lod`
+    fn sample_trilinear(&self, uv: &Vec2, lod: f32) -> Color {
+        let lod = lod.clamp(0.0, (self.levels.len() - 1) as f32);
+        let level = lod.floor() as usize;
+        if level + 1 >= self.levels.len() { return self.sample_bilinear(level, uv); }
+
+        lerp(&self.sample_bilinear(level, uv), &self.sample_bilinear(level + 1, uv), lod - level as f32)
+    }
+
+    /// Length of a UV space vector in pixels of the full image
+    fn get_texel_length(&self, axis: &Vec2) -> f32 {
+        let (width, height) = self.get_dimensions();
+        Vec2::new(axis.x * width as f32, axis.y * height as f32).magnitude()
+    }
+
+    /// Color at the given UV, without any footprint
+    pub fn sample(&self, uv: &Vec2) -> Color {
+        match self.filter {
+            Filter::Nearest => self.sample_nearest(0, uv),
+            _ => self.sample_bilinear(0, uv)
+        }
+    }
+
+    /// Color averaged over the ellipse with the given axes around `uv`
+    pub fn sample_footprint(&self, uv: &Vec2, footprint: &[Vec2; 2]) -> Color {
+        let (first, second) = (self.get_texel_length(&footprint[0]), self.get_texel_length(&footprint[1]));
+        let (major, minor, major_axis) = if first >= second {
+            (first, second, footprint[0])
+        } else {
+            (second, first, footprint[1])
+        };
+
+        match self.filter {
+            Filter::Nearest | Filter::Bilinear => self.sample(uv),
+            Filter::Trilinear => self.sample_trilinear(uv, major.max(1e-8).log2()),
+            Filter::Anisotropic => {
+                // Spread samples along the long axis, each covering a roughly round part of it
+                let count = (major / minor.max(1e-8)).ceil().clamp(1.0, MAX_ANISOTROPY);
+                let lod = (major / count).max(minor).max(1e-8).log2();
+
+                let mut color = Color::rgba(0.0, 0.0, 0.0, 0.0);
+                for i in 0..count as usize {
+                    let offset = (i as f32 + 0.5) / count - 0.5;
+                    let point = uv.add(&major_axis.mul_by(offset));
+                    color.add_mut(&self.sample_trilinear(&point, lod));
+                }
+
+                color.div_by(count)
+            }
+        }
+    }
+}
+
+impl MipLevel {
+    /// Averages blocks of 2x2 pixels, odd sizes repeat their last row or column
+    fn downsample(&self) -> Self {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+
+        let get = |x: u32, y: u32| self.pixels[(y.min(self.height - 1) * self.width + x.min(self.width - 1)) as usize];
+
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                get(x * 2, y * 2)
+                    .add(&get(x * 2 + 1, y * 2))
+                    .add(&get(x * 2, y * 2 + 1))
+                    .add(&get(x * 2 + 1, y * 2 + 1))
+                    .mul_by(0.25)
+            })
+            .collect();
+
+        Self { width, height, pixels }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn evaluate(&self, hit: &Hit) -> Color {
+        self.sample_footprint(&hit.uv, &hit.footprint)
+    }
+}
+
+fn wrap(coordinate: i64, size: i64, mode: WrapMode) -> usize {
+    let wrapped = match mode {
+        WrapMode::Repeat => coordinate.rem_euclid(size),
+        WrapMode::Clamp => coordinate.clamp(0, size - 1),
+        WrapMode::Mirror => {
+            // Every other repetition is flipped
+            let period = coordinate.rem_euclid(size * 2);
+            if period < size { period } else { size * 2 - 1 - period }
+        }
+    };
+
+    wrapped as usize
+}
+
+fn lerp(a: &Color, b: &Color, t: f32) -> Color {
+    a.mul_by(1.0 - t).add(&b.mul_by(t))
+}
+
+/// Decodes a gamma encoded sRGB value into linear light
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checker() -> ImageTexture {
+        let black = Color::rgb(0.0, 0.0, 0.0);
+        let white = Color::rgb(1.0, 1.0, 1.0);
+        ImageTexture::new(2, 2, vec![black, white, white, black])
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one pixel")]
+    fn rejects_empty_textures() {
+        ImageTexture::new(0, 4, Vec::new());
+    }
+
+    #[test]
+    fn filters_bilinearly() {
+        let texture = checker();
+
+        // Pixel centers return the pixel itself, halfway between them blends
+        assert_eq!(texture.sample(&Vec2::new(0.25, 0.25)).r, 0.0);
+        assert_eq!(texture.sample(&Vec2::new(0.75, 0.25)).r, 1.0);
+        assert!((texture.sample(&Vec2::new(0.5, 0.25)).r - 0.5).abs() < 1e-6);
+
+        let nearest = checker().set_filter(Filter::Nearest);
+        assert_eq!(nearest.sample(&Vec2::new(0.49, 0.1)).r, 0.0);
+        assert_eq!(nearest.sample(&Vec2::new(0.51, 0.1)).r, 1.0);
+    }
+
+    #[test]
+    fn wraps_coordinates() {
+        assert_eq!(wrap(-1, 4, WrapMode::Repeat), 3);
+        assert_eq!(wrap(5, 4, WrapMode::Repeat), 1);
+        assert_eq!(wrap(-1, 4, WrapMode::Clamp), 0);
+        assert_eq!(wrap(9, 4, WrapMode::Clamp), 3);
+        assert_eq!(wrap(4, 4, WrapMode::Mirror), 3);
+        assert_eq!(wrap(-1, 4, WrapMode::Mirror), 0);
+
+        // Wrapping around the edge blends with the opposite side, clamping doesn't
+        let texture = checker();
+        assert!((texture.sample(&Vec2::new(0.0, 0.25)).r - 0.5).abs() < 1e-6);
+        let texture = checker().set_wrap(WrapMode::Clamp);
+        assert_eq!(texture.sample(&Vec2::new(0.0, 0.25)).r, 0.0);
+    }
+
+    /// Rows alternating between black and white
+    fn stripes() -> ImageTexture {
+        let pixels = (0..16).map(|i| if (i / 4) % 2 == 0 { Color::rgb(0.0, 0.0, 0.0) } else { Color::rgb(1.0, 1.0, 1.0) });
+        ImageTexture::new(4, 4, pixels.collect())
+    }
+
+    #[test]
+    fn builds_mip_pyramid() {
+        let texture = stripes();
+        assert_eq!(texture.get_level_count(), 3);
+        assert_eq!(texture.levels[1].width, 2);
+        assert!(texture.levels[2].pixels.iter().all(|pixel| (pixel.r - 0.5).abs() < 1e-6));
+
+        let odd = ImageTexture::new(3, 1, vec![Color::rgb(1.0, 1.0, 1.0); 3]);
+        assert_eq!(odd.get_level_count(), 2);
+        assert_eq!(odd.levels[1].pixels[0].r, 1.0);
+    }
+
+    #[test]
+    fn footprints_pick_mip_levels() {
+        let texture = stripes();
+        let center = Vec2::new(0.125, 0.125);
+        let point = [Vec2::new(0.0, 0.0); 2];
+
+        // A point reads the full image, a footprint covering the image reads its average
+        assert_eq!(texture.sample_footprint(&center, &point).r, 0.0);
+        let wide = [Vec2::new(1.0, 0.0), Vec2::new(0.0, 1.0)];
+        assert!((texture.sample_footprint(&center, &wide).r - 0.5).abs() < 1e-6);
+
+        // Stretched along the stripes, trilinear blurs across them while anisotropic stays sharp
+        let stretched = [Vec2::new(1.0, 0.0), Vec2::new(0.0, 0.01)];
+        assert!((texture.sample_footprint(&center, &stretched).r - 0.5).abs() < 1e-6);
+        let anisotropic = stripes().set_filter(Filter::Anisotropic);
+        assert!(anisotropic.sample_footprint(&center, &stretched).r < 1e-6);
+    }
+
+    #[test]
+    fn decodes_srgb() {
+        assert_eq!(srgb_to_linear(0.0), 0.0);
+        assert!((srgb_to_linear(1.0) - 1.0).abs() < 1e-6);
+        assert!((srgb_to_linear(0.5) - 0.214).abs() < 1e-3);
+    }
+}
\ No newline at end of file
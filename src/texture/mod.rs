@@ -0,0 +1,42 @@
+mod image_texture;
+mod checker;
+mod noise;
+mod patterns;
+
+pub use image_texture::{ImageTexture, ColorSpace, WrapMode, Filter};
+pub use checker::Checker;
+pub use noise::{Noise, NoiseKind, noise, fbm, turbulence};
+pub use patterns::{Marble, Wood};
+
+use std::fmt::Debug;
+
+use crate::{
+    shapes::Hit,
+    util::{Color, vec::Vec3}
+};
+
+/// Varies a material property across a surface.
+/// [Material](crate::util::Material) takes them for its albedo, roughness, metallic, normals, bumps and opacity,
+/// scalar properties such as roughness are read from the red channel
+pub trait Texture: Debug + Send + Sync + 'static {
+    /// Value of the texture where the ray hit
+    fn evaluate(&self, hit: &Hit) -> Color;
+}
+
+/// Where procedural textures read their coordinates from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mapping {
+    /// Texture coordinates of the hit, as `(u, v, 0.0)`
+    Uv,
+    /// World space position of the hit, solid textures look carved out of the pattern
+    Position
+}
+
+impl Mapping {
+    pub fn get_point(&self, hit: &Hit) -> Vec3 {
+        match self {
+            Mapping::Uv => Vec3::new(hit.uv.x, hit.uv.y, 0.0),
+            Mapping::Position => hit.position
+        }
+    }
+}
\ No newline at end of file
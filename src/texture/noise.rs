@@ -0,0 +1,186 @@
+use super::{Mapping, Texture};
+use crate::{
+    shapes::Hit,
+    util::{Color, vec::*}
+};
+
+/// Gradients along the edges of a cube, picked per lattice point
+const GRADIENTS: [[f32; 3]; 12] = [
+    [1.0, 1.0, 0.0], [-1.0, 1.0, 0.0], [1.0, -1.0, 0.0], [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0], [-1.0, 0.0, 1.0], [1.0, 0.0, -1.0], [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0], [0.0, -1.0, 1.0], [0.0, 1.0, -1.0], [0.0, -1.0, -1.0]
+];
+
+/// Gradient (Perlin) noise, smooth and roughly within -1.0 - 1.0
+pub fn noise(point: &Vec3) -> f32 {
+    let cell = [point.x.floor(), point.y.floor(), point.z.floor()];
+    let offset = [point.x - cell[0], point.y - cell[1], point.z - cell[2]];
+    let cell = cell.map(|value| value as i32);
+
+    // Contribution of the gradient at one corner of the cell
+    let corner = |dx: i32, dy: i32, dz: i32| {
+        let gradient = GRADIENTS[(hash(cell[0] + dx, cell[1] + dy, cell[2] + dz) % 12) as usize];
+
+        gradient[0] * (offset[0] - dx as f32)
+            + gradient[1] * (offset[1] - dy as f32)
+            + gradient[2] * (offset[2] - dz as f32)
+    };
+
+    let [u, v, w] = offset.map(fade);
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+    let x00 = lerp(corner(0, 0, 0), corner(1, 0, 0), u);
+    let x10 = lerp(corner(0, 1, 0), corner(1, 1, 0), u);
+    let x01 = lerp(corner(0, 0, 1), corner(1, 0, 1), u);
+    let x11 = lerp(corner(0, 1, 1), corner(1, 1, 1), u);
+
+    lerp(lerp(x00, x10, v), lerp(x01, x11, v), w)
+}
+
+/// Fractal Brownian motion: octaves of noise, each `lacunarity` times finer and `gain` times weaker
+pub fn fbm(point: &Vec3, octaves: u32, lacunarity: f32, gain: f32) -> f32 {
+    let mut total = 0.0;
+    let mut frequency = 1.0;
+    let mut amplitude = 1.0;
+
+    for _ in 0..octaves {
+        total += amplitude * noise(&point.mul_by(frequency));
+        frequency *= lacunarity;
+        amplitude *= gain;
+    }
+
+    total
+}
+
+/// Like [fbm] with the absolute value of every octave, giving sharp creases
+pub fn turbulence(point: &Vec3, octaves: u32) -> f32 {
+    let mut total = 0.0;
+    let mut frequency = 1.0;
+    let mut amplitude = 1.0;
+
+    for _ in 0..octaves {
+        total += amplitude * noise(&point.mul_by(frequency)).abs();
+        frequency *= 2.0;
+        amplitude *= 0.5;
+    }
+
+    total
+}
+
+/// Smoothstep with zero first and second derivatives at both ends
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+/// Scrambles lattice coordinates into a pseudo random number
+fn hash(x: i32, y: i32, z: i32) -> u32 {
+    let mut h = (x as u32).wrapping_mul(0x8DA6_B343)
+        ^ (y as u32).wrapping_mul(0xD816_3841)
+        ^ (z as u32).wrapping_mul(0xCB1A_B31F);
+
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7FEB_352D);
+    h ^= h >> 15;
+    h
+}
+
+/// Which kind of fractal a [Noise] texture layers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoiseKind {
+    Fbm,
+    Turbulence
+}
+
+/// Blends between two colors following fractal noise
+#[derive(Debug, Clone, Copy)]
+pub struct Noise {
+    pub low: Color,
+    pub high: Color,
+    /// Features per unit of UV or space
+    pub scale: f32,
+    pub octaves: u32,
+    pub kind: NoiseKind,
+    pub mapping: Mapping
+}
+
+impl Noise {
+    pub fn new(low: Color, high: Color, scale: f32) -> Self {
+        Self { low, high, scale, octaves: 5, kind: NoiseKind::Fbm, mapping: Mapping::Position }
+    }
+
+    pub fn set_octaves(mut self, octaves: u32) -> Self {
+        self.octaves = octaves;
+        self
+    }
+
+    pub fn set_kind(mut self, kind: NoiseKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn set_mapping(mut self, mapping: Mapping) -> Self {
+        self.mapping = mapping;
+        self
+    }
+}
+
+impl Texture for Noise {
+    fn evaluate(&self, hit: &Hit) -> Color {
+        let point = self.mapping.get_point(hit).mul_by(self.scale);
+
+        // Both fractals are brought roughly into 0.0 - 1.0
+        let value = match self.kind {
+            NoiseKind::Fbm => 0.5 + 0.5 * fbm(&point, self.octaves, 2.0, 0.5),
+            NoiseKind::Turbulence => turbulence(&point, self.octaves)
+        };
+
+        self.low.mul_by(1.0 - value.clamp(0.0, 1.0)).add(&self.high.mul_by(value.clamp(0.0, 1.0)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit_at(position: Vec3, uv: Vec2) -> Hit {
+        Hit::new(1.0, position, Vec3::new(0.0, 1.0, 0.0)).set_uv(uv)
+    }
+
+    #[test]
+    fn noise_is_smooth_and_bounded() {
+        // Zero on every lattice point, where all the gradients are dotted with zero offsets
+        assert_eq!(noise(&Vec3::new(3.0, -2.0, 7.0)), 0.0);
+
+        let mut previous = noise(&Vec3::new(0.1, 0.2, 0.3));
+        for i in 1..1000 {
+            let point = Vec3::new(0.1 + i as f32 * 0.01, 0.2, 0.3 + i as f32 * 0.003);
+            let value = noise(&point);
+
+            assert!(value.abs() <= 1.0);
+            assert!((value - previous).abs() < 0.05);
+            assert_eq!(value, noise(&point));
+            previous = value;
+        }
+
+        let point = Vec3::new(1.3, 4.2, -0.7);
+        assert!(turbulence(&point, 4) >= 0.0);
+        assert_eq!(fbm(&point, 1, 2.0, 0.5), noise(&point));
+    }
+
+    #[test]
+    fn noise_stays_between_its_colors() {
+        let (dark, light) = (Color::rgb(0.1, 0.1, 0.1), Color::rgb(0.9, 0.9, 0.9));
+        let textures = [
+            Noise::new(dark, light, 3.0),
+            Noise::new(dark, light, 3.0).set_kind(NoiseKind::Turbulence).set_mapping(Mapping::Uv)
+        ];
+
+        for texture in &textures {
+            for i in 0..100 {
+                let t = i as f32 * 0.137;
+                let color = texture.evaluate(&hit_at(Vec3::new(t, t * 0.5, -t), Vec2::new(t, t * 0.3)));
+                assert!(color.r >= 0.1 - 1e-5 && color.r <= 0.9 + 1e-5);
+            }
+        }
+    }
+}
\ No newline at end of file
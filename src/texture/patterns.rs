@@ -0,0 +1,113 @@
+use std::f32::consts::PI;
+
+use super::{Mapping, Texture, noise::{noise, turbulence}};
+use crate::{
+    shapes::Hit,
+    util::{Color, vec::*}
+};
+
+/// Veined stone: bands along the x axis warped by turbulence
+#[derive(Debug, Clone, Copy)]
+pub struct Marble {
+    pub base: Color,
+    pub vein: Color,
+    /// Bands per unit of UV or space
+    pub scale: f32,
+    /// How strongly turbulence bends the bands
+    pub distortion: f32,
+    pub mapping: Mapping
+}
+
+impl Marble {
+    pub fn new(base: Color, vein: Color, scale: f32) -> Self {
+        Self { base, vein, scale, distortion: 5.0, mapping: Mapping::Position }
+    }
+
+    pub fn set_distortion(mut self, distortion: f32) -> Self {
+        self.distortion = distortion;
+        self
+    }
+
+    pub fn set_mapping(mut self, mapping: Mapping) -> Self {
+        self.mapping = mapping;
+        self
+    }
+}
+
+impl Texture for Marble {
+    fn evaluate(&self, hit: &Hit) -> Color {
+        let point = self.mapping.get_point(hit).mul_by(self.scale);
+        let band = (point.x * PI + self.distortion * turbulence(&point, 6)).sin();
+
+        // Sharpen the veins so most of the surface is the base color
+        let vein = (1.0 - band.abs()).powi(4);
+        self.base.mul_by(1.0 - vein).add(&self.vein.mul_by(vein))
+    }
+}
+
+/// Growth rings around the y axis, slightly wobbled by noise
+#[derive(Debug, Clone, Copy)]
+pub struct Wood {
+    pub light: Color,
+    pub dark: Color,
+    /// Rings per unit of distance from the axis
+    pub rings: f32,
+    /// How far noise pushes rings out of round
+    pub distortion: f32,
+    pub mapping: Mapping
+}
+
+impl Wood {
+    pub fn new(light: Color, dark: Color, rings: f32) -> Self {
+        Self { light, dark, rings, distortion: 0.3, mapping: Mapping::Position }
+    }
+
+    pub fn set_distortion(mut self, distortion: f32) -> Self {
+        self.distortion = distortion;
+        self
+    }
+
+    pub fn set_mapping(mut self, mapping: Mapping) -> Self {
+        self.mapping = mapping;
+        self
+    }
+}
+
+impl Texture for Wood {
+    fn evaluate(&self, hit: &Hit) -> Color {
+        let point = self.mapping.get_point(hit);
+        let radius = (point.x * point.x + point.z * point.z).sqrt();
+        let rings = radius * self.rings + self.distortion * noise(&point.mul_by(2.0));
+
+        // Each ring fades from light early wood into dark late wood
+        let t = rings.rem_euclid(1.0).powi(3);
+        self.light.mul_by(1.0 - t).add(&self.dark.mul_by(t))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit_at(position: Vec3, uv: Vec2) -> Hit {
+        Hit::new(1.0, position, Vec3::new(0.0, 1.0, 0.0)).set_uv(uv)
+    }
+
+    #[test]
+    fn patterns_stay_between_their_colors() {
+        let (dark, light) = (Color::rgb(0.1, 0.1, 0.1), Color::rgb(0.9, 0.9, 0.9));
+        let textures: [Box<dyn Texture>; 3] = [
+            Box::new(Marble::new(light, dark, 2.0)),
+            Box::new(Marble::new(light, dark, 2.0).set_mapping(Mapping::Uv)),
+            Box::new(Wood::new(light, dark, 8.0))
+        ];
+
+        for texture in &textures {
+            for i in 0..100 {
+                let t = i as f32 * 0.137;
+                let color = texture.evaluate(&hit_at(Vec3::new(t, t * 0.5, -t), Vec2::new(t, t * 0.3)));
+                assert!(color.r >= 0.1 - 1e-5 && color.r <= 0.9 + 1e-5);
+            }
+        }
+    }
+}
\ No newline at end of file
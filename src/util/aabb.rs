@@ -0,0 +1,127 @@
+use crate::util::{matrix::Matrix4, vec::*};
+
+/// Axis-aligned bounding box
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3
+}
+
+impl Default for Aabb {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    /// A box containing nothing, growing it by anything results in that thing's bounds
+    pub fn empty() -> Self {
+        Self {
+            min: Vec3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            max: Vec3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY)
+        }
+    }
+
+    /// Create the smallest box containing all of `points`
+    pub fn from_points(points: &[Vec3]) -> Self {
+        points.iter().fold(Self::empty(), |bounds, point| bounds.grow(point))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    /// Returns the box expanded to contain `point`
+    pub fn grow(&self, point: &Vec3) -> Self {
+        Self {
+            min: Vec3::new(self.min.x.min(point.x), self.min.y.min(point.y), self.min.z.min(point.z)),
+            max: Vec3::new(self.max.x.max(point.x), self.max.y.max(point.y), self.max.z.max(point.z))
+        }
+    }
+
+    /// Returns the box containing both `self` and `other`
+    pub fn union(&self, other: &Self) -> Self {
+        // Growing by the corners of an empty box would make it infinite
+        if self.is_empty() { return *other; }
+        if other.is_empty() { return *self; }
+
+        self.grow(&other.min).grow(&other.max)
+    }
+
+    /// Returns the box enlarged by `amount` on every side
+    pub fn pad(&self, amount: f32) -> Self {
+        Self {
+            min: self.min.sub_by(amount),
+            max: self.max.add_by(amount)
+        }
+    }
+
+    pub fn get_extent(&self) -> Vec3 {
+        self.max.sub(&self.min)
+    }
+
+    pub fn get_centroid(&self) -> Vec3 {
+        self.min.add(&self.max).mul_by(0.5)
+    }
+
+    pub fn get_surface_area(&self) -> f32 {
+        if self.is_empty() { return 0.0; }
+
+        let extent = self.get_extent();
+        2.0 * (extent.x * extent.y + extent.y * extent.z + extent.z * extent.x)
+    }
+
+    /// Index of the axis the box is longest on, 0 = x, 1 = y, 2 = z
+    pub fn get_longest_axis(&self) -> usize {
+        let extent = self.get_extent();
+
+        if extent.x > extent.y && extent.x > extent.z { 0 }
+        else if extent.y > extent.z { 1 }
+        else { 2 }
+    }
+
+    /// Returns the bounds of the box after being moved by `transform`
+    pub fn transform(&self, transform: &Matrix4) -> Self {
+        if self.is_empty() { return *self; }
+
+        let corners: Vec<Vec3> = (0..8)
+            .map(|i| Vec3::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z }
+            ))
+            .map(|corner| transform.transform_point(&corner))
+            .collect();
+
+        Self::from_points(&corners)
+    }
+
+    /// Slab test against a ray given as its origin and `1.0 / direction`.
+    /// Returns the distance the ray enters the box at, if it does so before `max_distance`
+    pub fn intersect(&self, origin: &Vec3, inverse_direction: &Vec3, max_distance: f32) -> Option<f32> {
+        self.clip(origin, inverse_direction, max_distance).map(|(enter, _)| enter)
+    }
+
+    /// Like [Aabb::intersect], but also returns the distance the ray leaves the box at,
+    /// capped to `max_distance`
+    pub fn clip(&self, origin: &Vec3, inverse_direction: &Vec3, max_distance: f32) -> Option<(f32, f32)> {
+        let near = self.min.sub(origin).mul(inverse_direction);
+        let far = self.max.sub(origin).mul(inverse_direction);
+
+        let enter = near.x.min(far.x)
+            .max(near.y.min(far.y))
+            .max(near.z.min(far.z))
+            .max(0.0);
+
+        let exit = near.x.max(far.x)
+            .min(near.y.max(far.y))
+            .min(near.z.max(far.z))
+            .min(max_distance);
+
+        if enter <= exit { Some((enter, exit)) } else { None }
+    }
+}
\ No newline at end of file
@@ -1,10 +1,94 @@
-use crate::util::Color;
+use std::sync::Arc;
 
+use crate::{
+    bsdf::{Bsdf, Dielectric, Dispersion, Frame, Mix, Principled, Specular, ThinFilm, face_forward},
+    shapes::Hit,
+    texture::Texture,
+    util::{Color, Wavelengths, random, vec::*}
+};
+
+/// Light scattering around under the surface before leaving it, as in skin, wax or milk
 #[derive(Debug, Clone, Copy)]
+pub struct Subsurface {
+    /// Color the surface ends up looking once light has bounced around inside
+    pub albedo: Color,
+    /// Average distance light travels inside between scattering events, per channel
+    pub mean_free_path: Color
+}
+
+impl Subsurface {
+    pub fn new(albedo: Color, mean_free_path: Color) -> Self {
+        Self { albedo, mean_free_path }
+    }
+
+    /// Scattering and extinction coefficients of the medium inside.
+    /// The albedo seen from outside is remapped to the albedo of single scattering events (Chiang et al. 2016)
+    pub fn get_coefficients(&self) -> (Color, Color) {
+        let remap = |albedo: f32, distance: f32| {
+            let albedo = albedo.clamp(0.0, 0.999);
+            let single = 1.0 - (albedo * (-5.09406 + albedo * (2.61188 - albedo * 4.31805))).exp();
+            let scale = 1.9 - albedo + 3.5 * (albedo - 0.8).powi(2);
+            let extinction = 1.0 / (distance * scale).max(1e-8);
+
+            (extinction * single, extinction)
+        };
+
+        let (r, g, b) = (
+            remap(self.albedo.r, self.mean_free_path.r),
+            remap(self.albedo.g, self.mean_free_path.g),
+            remap(self.albedo.b, self.mean_free_path.b)
+        );
+
+        (Color::rgb(r.0, g.0, b.0), Color::rgb(r.1, g.1, b.1))
+    }
+}
+
+/// How partly transparent surfaces decide whether a ray passes through them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlphaMode {
+    /// Rays pass through with a probability of one minus the opacity, soft edges blend without bias
+    Stochastic,
+    /// Rays pass through wherever the opacity is below the threshold, giving crisp edges
+    Cutoff(f32)
+}
+
+/// Below this roughness, metals are treated as perfect mirrors
+const MIN_ROUGHNESS: f32 = 1e-3;
+
+#[derive(Debug, Clone)]
 pub struct Material {
     pub albedo: Color,
     pub roughness: f32,
-    pub metallic: f32
+    pub metallic: f32,
+    /// Index of refraction of transmissive materials
+    pub ior: f32,
+    /// Makes `ior` depend on the wavelength in spectral rendering, see [Material::set_dispersion]
+    pub dispersion: Option<Dispersion>,
+    /// How much light passes through the surface instead of bouncing off it, 0.0 - 1.0
+    pub transmission: f32,
+    /// Color white light fades to after travelling one unit inside the material
+    pub absorption: Option<Color>,
+    /// Color of the light the surface gives off, scaled by `emission_strength`
+    pub emission: Color,
+    pub emission_strength: f32,
+    /// Replace the constants above across the surface, see [Material::evaluate]
+    pub albedo_texture: Option<Arc<dyn Texture>>,
+    pub roughness_texture: Option<Arc<dyn Texture>>,
+    pub metallic_texture: Option<Arc<dyn Texture>>,
+    /// Tangent space normals, red along increasing `u` and green towards the top of the image
+    pub normal_texture: Option<Arc<dyn Texture>>,
+    /// Height read from the red channel, scaled by `bump_strength`
+    pub bump_texture: Option<Arc<dyn Texture>>,
+    pub bump_strength: f32,
+    /// Makes light entering the surface wander around inside it, see [Subsurface]
+    pub subsurface: Option<Subsurface>,
+    /// Iridescent coating over the dielectric or metal reflections, see [Material::set_thin_film]
+    pub thin_film: Option<ThinFilm>,
+    /// How much of the surface is there at all, rays pass through the rest without touching it
+    pub opacity: f32,
+    /// Scales `opacity` by its alpha channel, e.g. the cut out shape of a leaf
+    pub opacity_texture: Option<Arc<dyn Texture>>,
+    pub alpha_mode: AlphaMode
 }
 
 impl Default for Material {
@@ -12,13 +96,438 @@ impl Default for Material {
         Self {
             albedo: Color::rgb(0.0, 0.0, 0.0),
             roughness: 1.0,
-            metallic: 0.0
+            metallic: 0.0,
+            ior: 1.5,
+            dispersion: None,
+            transmission: 0.0,
+            absorption: None,
+            emission: Color::rgb(0.0, 0.0, 0.0),
+            emission_strength: 1.0,
+            albedo_texture: None,
+            roughness_texture: None,
+            metallic_texture: None,
+            normal_texture: None,
+            bump_texture: None,
+            bump_strength: 1.0,
+            subsurface: None,
+            thin_film: None,
+            opacity: 1.0,
+            opacity_texture: None,
+            alpha_mode: AlphaMode::Stochastic
         }
     }
 }
 
 impl Material {
     pub fn new(albedo: Color, roughness: f32, metallic: f32) -> Self {
-        Self { albedo, roughness, metallic }
+        Self { albedo, roughness, metallic, ..Default::default() }
+    }
+
+    /// Clear glass-like material with the given index of refraction
+    pub fn dielectric(ior: f32) -> Self {
+        Self {
+            albedo: Color::rgb(1.0, 1.0, 1.0),
+            roughness: 0.0,
+            ior,
+            transmission: 1.0,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_ior(mut self, ior: f32) -> Self {
+        self.ior = ior;
+        self
+    }
+
+    /// Splits light into its colors when rendering spectrally.
+    /// Also sets `ior` to the index at the sodium D line, used when rendering in RGB
+    pub fn set_dispersion(mut self, dispersion: Dispersion) -> Self {
+        self.ior = dispersion.get_ior(Dispersion::SODIUM_D);
+        self.dispersion = Some(dispersion);
+        self
+    }
+
+    pub fn set_transmission(mut self, transmission: f32) -> Self {
+        self.transmission = transmission;
+        self
+    }
+
+    pub fn set_absorption(mut self, absorption: Color) -> Self {
+        self.absorption = Some(absorption);
+        self
+    }
+
+    /// Light entering the surface scatters around inside of it, the shape should be closed
+    pub fn set_subsurface(mut self, albedo: Color, mean_free_path: Color) -> Self {
+        self.subsurface = Some(Subsurface::new(albedo, mean_free_path));
+        self
+    }
+
+    /// Coats the surface with a transparent film `thickness` nanometres thick,
+    /// giving soap bubble and oil slick colors that shift with the viewing angle
+    pub fn set_thin_film(mut self, thickness: f32, ior: f32) -> Self {
+        self.thin_film = Some(ThinFilm::new(thickness, ior));
+        self
+    }
+
+    /// Makes the surface glow, turning any shape using the material into a light source
+    pub fn set_emission(mut self, emission: Color, strength: f32) -> Self {
+        self.emission = emission;
+        self.emission_strength = strength;
+        self
+    }
+
+    pub fn set_albedo_texture<T: Texture>(mut self, texture: T) -> Self {
+        self.albedo_texture = Some(Arc::new(texture));
+        self
+    }
+
+    /// Roughness is read from the red channel of the texture
+    pub fn set_roughness_texture<T: Texture>(mut self, texture: T) -> Self {
+        self.roughness_texture = Some(Arc::new(texture));
+        self
+    }
+
+    /// Metallic is read from the red channel of the texture
+    pub fn set_metallic_texture<T: Texture>(mut self, texture: T) -> Self {
+        self.metallic_texture = Some(Arc::new(texture));
+        self
+    }
+
+    /// Normals should be stored linearly, see [ColorSpace::Linear](crate::texture::ColorSpace::Linear)
+    pub fn set_normal_texture<T: Texture>(mut self, texture: T) -> Self {
+        self.normal_texture = Some(Arc::new(texture));
+        self
+    }
+
+    pub fn set_bump_texture<T: Texture>(mut self, texture: T, strength: f32) -> Self {
+        self.bump_texture = Some(Arc::new(texture));
+        self.bump_strength = strength;
+        self
+    }
+
+    /// Lets rays through part of the surface, 0.0 - 1.0
+    pub fn set_opacity(mut self, opacity: f32) -> Self {
+        self.opacity = opacity;
+        self
+    }
+
+    /// Opacity is read from the alpha channel of the texture
+    pub fn set_opacity_texture<T: Texture>(mut self, texture: T) -> Self {
+        self.opacity_texture = Some(Arc::new(texture));
+        self
+    }
+
+    pub fn set_alpha_mode(mut self, alpha_mode: AlphaMode) -> Self {
+        self.alpha_mode = alpha_mode;
+        self
+    }
+
+    /// Whether parts of the surface can be see-through
+    pub fn is_cutout(&self) -> bool {
+        self.opacity < 1.0 || self.opacity_texture.is_some()
+    }
+
+    /// How much of the surface is there at a hit
+    pub fn get_opacity(&self, hit: &Hit) -> f32 {
+        let alpha = self.opacity_texture.as_ref().map_or(1.0, |texture| texture.evaluate(hit).a);
+        (self.opacity * alpha).clamp(0.0, 1.0)
+    }
+
+    /// Whether a ray reaching the surface at a hit ignores it and carries on
+    pub fn passes_through(&self, hit: &Hit) -> bool {
+        if !self.is_cutout() { return false; }
+
+        let opacity = self.get_opacity(hit);
+        match self.alpha_mode {
+            AlphaMode::Stochastic => opacity < 1.0 && random() >= opacity,
+            AlphaMode::Cutoff(threshold) => opacity < threshold
+        }
+    }
+
+    /// Normal used for shading at a hit, perturbed by the normal and bump maps
+    pub fn get_shading_normal(&self, hit: &Hit) -> Vec3 {
+        let mut normal = hit.normal;
+
+        if let Some(texture) = &self.bump_texture {
+            normal = self.get_bumped_normal(texture.as_ref(), hit);
+        }
+
+        if let Some(texture) = &self.normal_texture {
+            let (tangent, bitangent) = get_tangent_frame(&normal, hit);
+            let value = texture.evaluate(hit);
+
+            // Stored in 0.0 - 1.0, the green channel points up the image, towards decreasing `v`
+            let local = Vec3::new(value.r * 2.0 - 1.0, value.g * 2.0 - 1.0, value.b * 2.0 - 1.0);
+            let perturbed = tangent.mul_by(local.x)
+                .sub(&bitangent.mul_by(local.y))
+                .add(&normal.mul_by(local.z))
+                .normalize();
+
+            if perturbed.magnitude() > 0.0 { normal = perturbed; }
+        }
+
+        normal
+    }
+
+    /// Moves the surface along its normal by the height and derives the normal of the result
+    fn get_bumped_normal(&self, texture: &dyn Texture, hit: &Hit) -> Vec3 {
+        const DELTA: f32 = 5e-4;
+
+        let height_at = |du: f32, dv: f32| {
+            let shifted = Hit { uv: Vec2::new(hit.uv.x + du, hit.uv.y + dv), ..hit.clone() };
+            texture.evaluate(&shifted).r * self.bump_strength
+        };
+
+        let height = height_at(0.0, 0.0);
+        let dhdu = (height_at(DELTA, 0.0) - height) / DELTA;
+        let dhdv = (height_at(0.0, DELTA) - height) / DELTA;
+
+        let (dpdu, dpdv) = if hit.dpdu.magnitude() > 0.0 {
+            (hit.dpdu, hit.dpdv)
+        } else {
+            get_tangent_frame(&hit.normal, hit)
+        };
+
+        let bumped = dpdu.add(&hit.normal.mul_by(dhdu))
+            .cross(&dpdv.add(&hit.normal.mul_by(dhdv)))
+            .normalize();
+
+        // Keep the normal on the same side as the surface's, whichever way the UVs wind
+        if bumped.magnitude() == 0.0 { return hit.normal; }
+        face_forward(&bumped, &hit.normal)
+    }
+
+    /// The material at a hit, with every textured property replaced by its value there
+    pub fn evaluate(&self, hit: &Hit) -> Self {
+        let mut material = self.clone();
+
+        if let Some(texture) = material.albedo_texture.take() {
+            material.albedo = texture.evaluate(hit);
+        }
+
+        if let Some(texture) = material.roughness_texture.take() {
+            material.roughness = texture.evaluate(hit).r;
+        }
+
+        if let Some(texture) = material.metallic_texture.take() {
+            material.metallic = texture.evaluate(hit).r;
+        }
+
+        material
+    }
+
+    /// The material as seen at the given wavelengths, with every color replaced by its spectrum there.
+    /// Dispersive materials take the index of refraction of the hero wavelength
+    pub fn to_spectral(&self, wavelengths: &Wavelengths) -> Self {
+        let mut material = self.clone();
+
+        material.albedo = wavelengths.from_rgb(&self.albedo);
+        material.emission = wavelengths.from_rgb(&self.emission);
+        material.absorption = self.absorption.map(|color| wavelengths.from_rgb(&color));
+        material.subsurface = self.subsurface.map(|subsurface| Subsurface::new(
+            wavelengths.from_rgb(&subsurface.albedo),
+            wavelengths.from_rgb(&subsurface.mean_free_path)
+        ));
+
+        if let Some(dispersion) = &self.dispersion {
+            material.ior = dispersion.get_ior(wavelengths.get_hero());
+        }
+
+        material.thin_film = self.thin_film.map(|film| film.set_wavelengths(wavelengths.wavelengths));
+
+        material
+    }
+
+    /// Light given off by the front of the surface
+    pub fn get_emission(&self) -> Color {
+        self.emission.mul_by(self.emission_strength)
+    }
+
+    pub fn is_emissive(&self) -> bool {
+        let emission = self.get_emission();
+        emission.r > 0.0 || emission.g > 0.0 || emission.b > 0.0
+    }
+
+    /// Fraction of the light left after travelling `distance` inside the material (Beer–Lambert)
+    pub fn get_transmittance(&self, distance: f32) -> Color {
+        match self.absorption {
+            Some(color) => Color::rgb(
+                color.r.max(0.0).powf(distance),
+                color.g.max(0.0).powf(distance),
+                color.b.max(0.0).powf(distance)
+            ),
+            None => Color::rgb(1.0, 1.0, 1.0)
+        }
+    }
+
+    /// Builds the [Bsdf] describing how the material scatters light.
+    /// `metallic` blends from a diffuse surface under a clear coat to a conductor tinted by the albedo,
+    /// both with GGX reflections `roughness` blurs. `transmission` blends towards a dielectric
+    pub fn get_bsdf(&self) -> Box<dyn Bsdf> {
+        self.build_bsdf(false)
+    }
+
+    /// [Material::get_bsdf] for a material converted with [Material::to_spectral],
+    /// where dispersive surfaces only let the hero wavelength through
+    pub fn get_spectral_bsdf(&self) -> Box<dyn Bsdf> {
+        self.build_bsdf(self.dispersion.is_some())
+    }
+
+    fn build_bsdf(&self, is_hero_only: bool) -> Box<dyn Bsdf> {
+        let transmission = self.transmission.clamp(0.0, 1.0);
+        let mut dielectric = Dielectric::new(self.ior, self.albedo).set_hero_only(is_hero_only);
+        if let Some(film) = self.thin_film {
+            dielectric = dielectric.set_thin_film(film);
+        }
+
+        let dielectric = Box::new(dielectric);
+        if transmission >= 1.0 { return dielectric; }
+
+        let base = self.get_opaque_bsdf();
+        if transmission <= 0.0 { return base; }
+
+        Box::new(Mix::new(base, dielectric, transmission))
+    }
+
+    fn get_opaque_bsdf(&self) -> Box<dyn Bsdf> {
+        // Smooth metals are mirrors, which GGX can't narrow down to
+        if self.metallic >= 1.0 && self.roughness < MIN_ROUGHNESS {
+            let specular = Specular::new(self.albedo);
+            return match self.thin_film {
+                Some(film) => Box::new(specular.set_thin_film(film)),
+                None => Box::new(specular)
+            };
+        }
+
+        let principled = Principled::new(self.albedo, self.roughness, self.metallic);
+        match self.thin_film {
+            Some(film) => Box::new(principled.set_thin_film(film)),
+            None => Box::new(principled)
+        }
+    }
+}
+
+/// Unit tangent and bitangent along increasing `u` and `v`, perpendicular to `normal`.
+/// Shapes without UV derivatives get an arbitrary frame
+fn get_tangent_frame(normal: &Vec3, hit: &Hit) -> (Vec3, Vec3) {
+    let tangent = hit.dpdu.sub(&normal.mul_by(normal.dot(&hit.dpdu)));
+    if tangent.magnitude() < 1e-8 {
+        let frame = Frame::new(normal);
+        return (frame.tangent, frame.bitangent);
+    }
+
+    let tangent = tangent.normalize();
+    let bitangent = normal.cross(&tangent);
+
+    // Mirrored UVs run `v` the other way around the normal
+    if bitangent.dot(&hit.dpdv) < 0.0 { (tangent, bitangent.invert()) } else { (tangent, bitangent) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        shapes::{Shape, Sphere},
+        util::{Ray, vec::Vec2}
+    };
+
+    #[derive(Debug)]
+    struct Constant(Color);
+
+    impl Texture for Constant {
+        fn evaluate(&self, _hit: &Hit) -> Color { self.0 }
+    }
+
+    /// Height rising along `u`
+    #[derive(Debug)]
+    struct Ramp;
+
+    impl Texture for Ramp {
+        fn evaluate(&self, hit: &Hit) -> Color { Color::rgb(hit.uv.x, hit.uv.x, hit.uv.x) }
+    }
+
+    fn sphere_hit() -> Hit {
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, Material::default());
+        let ray = Ray::new(Vec3::new(5.0, 0.3, 0.2), Vec3::new(-1.0, 0.0, 0.0));
+        sphere.intersect(&ray).unwrap()
+    }
+
+    #[test]
+    fn sphere_tangents_follow_uvs() {
+        let hit = sphere_hit();
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, Material::default());
+
+        assert!(hit.dpdu.dot(&hit.normal).abs() < 1e-5);
+        assert!(hit.dpdv.dot(&hit.normal).abs() < 1e-5);
+
+        // Stepping along the tangents moves the UVs the expected way
+        let along = |step: Vec3| {
+            let target = hit.position.add(&step.mul_by(1e-3));
+            let ray = Ray::new(target.mul_by(3.0), target.mul_by(-2.0));
+            sphere.intersect(&ray).unwrap().uv.sub(&hit.uv)
+        };
+
+        let du = along(hit.dpdu);
+        let dv = along(hit.dpdv);
+        assert!((du.x - 1e-3).abs() < 1e-4 && du.y.abs() < 1e-4);
+        assert!((dv.y - 1e-3).abs() < 1e-4 && dv.x.abs() < 1e-4);
+    }
+
+    #[test]
+    fn normal_maps_tilt_the_normal() {
+        let hit = sphere_hit();
+
+        // A flat normal map leaves the normal untouched
+        let flat = Material::default().set_normal_texture(Constant(Color::rgb(0.5, 0.5, 1.0)));
+        assert!(flat.get_shading_normal(&hit).sub(&hit.normal).magnitude() < 1e-5);
+
+        // Red leans along increasing `u`, green against increasing `v`
+        let tangent = hit.dpdu.normalize();
+        let bitangent = hit.dpdv.normalize();
+        let leaning = Material::default().set_normal_texture(Constant(Color::rgb(1.0, 0.5, 0.5)));
+        assert!(leaning.get_shading_normal(&hit).sub(&tangent).magnitude() < 1e-4);
+        let leaning = Material::default().set_normal_texture(Constant(Color::rgb(0.5, 1.0, 0.5)));
+        assert!(leaning.get_shading_normal(&hit).add(&bitangent).magnitude() < 1e-4);
+    }
+
+    #[test]
+    fn opacity_lets_rays_through() {
+        let hit = sphere_hit();
+
+        let solid = Material::default();
+        assert!(!solid.is_cutout() && !solid.passes_through(&hit));
+
+        // Seven in eight rays go through a surface an eighth opaque
+        let count = 10000;
+        let sheer = Material::default().set_opacity(0.25).set_opacity_texture(Constant(Color::rgba(1.0, 1.0, 1.0, 0.5)));
+        assert!((sheer.get_opacity(&hit) - 0.125).abs() < 1e-6);
+        let passed = (0..count).filter(|_| sheer.passes_through(&hit)).count();
+        assert!((passed as f32 / count as f32 - 0.875).abs() < 0.02);
+
+        // Cutoffs decide the same way every time
+        let cutout = Material::default().set_opacity_texture(Constant(Color::rgba(1.0, 1.0, 1.0, 0.4)));
+        assert!(cutout.clone().set_alpha_mode(AlphaMode::Cutoff(0.5)).passes_through(&hit));
+        assert!(!cutout.set_alpha_mode(AlphaMode::Cutoff(0.3)).passes_through(&hit));
+    }
+
+    #[test]
+    fn bump_maps_tilt_against_the_slope() {
+        let hit = sphere_hit();
+        let tangent = hit.dpdu.normalize();
+
+        let flat = Material::default().set_bump_texture(Constant(Color::rgb(0.5, 0.5, 0.5)), 1.0);
+        assert!(flat.get_shading_normal(&hit).sub(&hit.normal).magnitude() < 1e-5);
+
+        // Ground rising along `u` faces back towards decreasing `u`
+        let bumped = Material::default().set_bump_texture(Ramp, 1.0).get_shading_normal(&hit);
+        assert!((bumped.magnitude() - 1.0).abs() < 1e-5);
+        assert!(bumped.dot(&tangent) < -0.05);
+        assert!(bumped.dot(&hit.normal) > 0.0);
+
+        // Without UVs there's nothing to bump along
+        let plain = Hit::new(1.0, hit.position, hit.normal).set_uv(Vec2::new(0.5, 0.5));
+        let bumped = Material::default().set_bump_texture(Ramp, 1.0).get_shading_normal(&plain);
+        assert!(bumped.dot(&plain.normal) > 0.0);
     }
 }
\ No newline at end of file
@@ -1,20 +1,499 @@
 #![allow(dead_code)]
+use crate::util::vec::*;
+
+/// Row-major 4x4 matrix, `data[row][column]`, meant to multiply column vectors
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Matrix4 {
     pub data: [[f32; 4]; 4]
 }
 
+/// Row-major 3x3 matrix, `data[row][column]`, meant to multiply column vectors
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Matrix3 {
     pub data: [[f32; 3]; 3]
 }
 
+impl Default for Matrix3 {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
 impl Matrix3 {
     pub fn new(data: [[f32; 3]; 3]) -> Self {
         Self { data }
     }
+
+    /// Create the identity matrix
+    pub fn identity() -> Self {
+        Self::new([
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0]
+        ])
+    }
+
+    /// Create a matrix that scales each axis by the matching component of `factor`
+    pub fn scaling(factor: Vec3) -> Self {
+        Self::new([
+            [factor.x, 0.0, 0.0],
+            [0.0, factor.y, 0.0],
+            [0.0, 0.0, factor.z]
+        ])
+    }
+
+    /// Create a matrix that rotates `angle` radians counter-clockwise around `axis`
+    pub fn rotation(axis: Vec3, angle: f32) -> Self {
+        Matrix4::rotation(axis, angle).to_matrix3()
+    }
+
+    /// Returns the result of `self * other`, meaning `other` is applied first
+    pub fn mul(&self, other: &Self) -> Self {
+        let mut data = [[0.0; 3]; 3];
+
+        for (row, values) in data.iter_mut().enumerate() {
+            for (column, value) in values.iter_mut().enumerate() {
+                *value = (0..3)
+                    .map(|i| self.data[row][i] * other.data[i][column])
+                    .sum();
+            }
+        }
+
+        Self::new(data)
+    }
+
+    /// Returns the result of multiplying the matrix with the column vector `vector`
+    pub fn mul_vec3(&self, vector: &Vec3) -> Vec3 {
+        let m = &self.data;
+
+        Vec3::new(
+            m[0][0] * vector.x + m[0][1] * vector.y + m[0][2] * vector.z,
+            m[1][0] * vector.x + m[1][1] * vector.y + m[1][2] * vector.z,
+            m[2][0] * vector.x + m[2][1] * vector.y + m[2][2] * vector.z
+        )
+    }
+
+    /// Swap the rows and columns of the matrix
+    pub fn transpose(&self) -> Self {
+        let mut data = [[0.0; 3]; 3];
+
+        for (row, values) in data.iter_mut().enumerate() {
+            for (column, value) in values.iter_mut().enumerate() {
+                *value = self.data[column][row];
+            }
+        }
+
+        Self::new(data)
+    }
+
+    pub fn determinant(&self) -> f32 {
+        let m = &self.data;
+
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    /// Returns the inverse of the matrix, or `None` if it is singular
+    pub fn inverse(&self) -> Option<Self> {
+        let determinant = self.determinant();
+        // Small scales have tiny determinants too, so only exactly singular matrices are rejected
+        if determinant == 0.0 || !(1.0 / determinant).is_finite() { return None; }
+
+        let m = &self.data;
+        let inv = 1.0 / determinant;
+
+        // Transposed matrix of cofactors divided by the determinant
+        Some(Self::new([
+            [
+                (m[1][1] * m[2][2] - m[1][2] * m[2][1]) * inv,
+                (m[0][2] * m[2][1] - m[0][1] * m[2][2]) * inv,
+                (m[0][1] * m[1][2] - m[0][2] * m[1][1]) * inv
+            ],
+            [
+                (m[1][2] * m[2][0] - m[1][0] * m[2][2]) * inv,
+                (m[0][0] * m[2][2] - m[0][2] * m[2][0]) * inv,
+                (m[0][2] * m[1][0] - m[0][0] * m[1][2]) * inv
+            ],
+            [
+                (m[1][0] * m[2][1] - m[1][1] * m[2][0]) * inv,
+                (m[0][1] * m[2][0] - m[0][0] * m[2][1]) * inv,
+                (m[0][0] * m[1][1] - m[0][1] * m[1][0]) * inv
+            ]
+        ]))
+    }
+
+    /// Embed the matrix in the top left corner of a [Matrix4]
+    pub fn to_matrix4(&self) -> Matrix4 {
+        let m = &self.data;
+
+        Matrix4::new([
+            [m[0][0], m[0][1], m[0][2], 0.0],
+            [m[1][0], m[1][1], m[1][2], 0.0],
+            [m[2][0], m[2][1], m[2][2], 0.0],
+            [0.0, 0.0, 0.0, 1.0]
+        ])
+    }
+}
+
+impl Default for Matrix4 {
+    fn default() -> Self {
+        Self::identity()
+    }
 }
 
 impl Matrix4 {
     pub fn new(data: [[f32; 4]; 4]) -> Self {
         Self { data }
     }
-}
\ No newline at end of file
+
+    /// Create the identity matrix
+    pub fn identity() -> Self {
+        Self::new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0]
+        ])
+    }
+
+    /// Create a matrix that moves points by `offset`
+    pub fn translation(offset: Vec3) -> Self {
+        Self::new([
+            [1.0, 0.0, 0.0, offset.x],
+            [0.0, 1.0, 0.0, offset.y],
+            [0.0, 0.0, 1.0, offset.z],
+            [0.0, 0.0, 0.0, 1.0]
+        ])
+    }
+
+    /// Create a matrix that scales each axis by the matching component of `factor`
+    pub fn scaling(factor: Vec3) -> Self {
+        Self::new([
+            [factor.x, 0.0, 0.0, 0.0],
+            [0.0, factor.y, 0.0, 0.0],
+            [0.0, 0.0, factor.z, 0.0],
+            [0.0, 0.0, 0.0, 1.0]
+        ])
+    }
+
+    /// Create a matrix that rotates `angle` radians counter-clockwise around `axis`
+    pub fn rotation(axis: Vec3, angle: f32) -> Self {
+        let axis = axis.normalize();
+        let (x, y, z) = (axis.x, axis.y, axis.z);
+        let (sin, cos) = angle.sin_cos();
+        let t = 1.0 - cos;
+
+        Self::new([
+            [t * x * x + cos, t * x * y - sin * z, t * x * z + sin * y, 0.0],
+            [t * x * y + sin * z, t * y * y + cos, t * y * z - sin * x, 0.0],
+            [t * x * z - sin * y, t * y * z + sin * x, t * z * z + cos, 0.0],
+            [0.0, 0.0, 0.0, 1.0]
+        ])
+    }
+
+    /// Create an OpenGL style perspective projection looking down -Z,
+    /// mapping depth between `near` and `far` to -1.0 - 1.0.
+    /// `fov` is the vertical field of view in radians
+    pub fn perspective(fov: f32, aspect_ratio: f32, near: f32, far: f32) -> Self {
+        let focal_length = 1.0 / (fov / 2.0).tan();
+        let depth = near - far;
+
+        Self::new([
+            [focal_length / aspect_ratio, 0.0, 0.0, 0.0],
+            [0.0, focal_length, 0.0, 0.0],
+            [0.0, 0.0, (far + near) / depth, (2.0 * far * near) / depth],
+            [0.0, 0.0, -1.0, 0.0]
+        ])
+    }
+
+    /// Create a view matrix which moves `eye` to the origin looking down -Z at `target`
+    pub fn look_at(eye: Vec3, target: Vec3, up: Vec3) -> Self {
+        let rotation = Vec3::look_at(eye, target, up).to_matrix4();
+        rotation.mul(&Self::translation(eye.invert()))
+    }
+
+    /// Returns the result of `self * other`, meaning `other` is applied first
+    pub fn mul(&self, other: &Self) -> Self {
+        let mut data = [[0.0; 4]; 4];
+
+        for (row, values) in data.iter_mut().enumerate() {
+            for (column, value) in values.iter_mut().enumerate() {
+                *value = (0..4)
+                    .map(|i| self.data[row][i] * other.data[i][column])
+                    .sum();
+            }
+        }
+
+        Self::new(data)
+    }
+
+    /// Swap the rows and columns of the matrix
+    pub fn transpose(&self) -> Self {
+        let mut data = [[0.0; 4]; 4];
+
+        for (row, values) in data.iter_mut().enumerate() {
+            for (column, value) in values.iter_mut().enumerate() {
+                *value = self.data[column][row];
+            }
+        }
+
+        Self::new(data)
+    }
+
+    /// 2x2 sub-determinants of the top two (`s`) and bottom two (`c`) rows
+    fn sub_determinants(&self) -> ([f32; 6], [f32; 6]) {
+        let m = &self.data;
+
+        let s = [
+            m[0][0] * m[1][1] - m[1][0] * m[0][1],
+            m[0][0] * m[1][2] - m[1][0] * m[0][2],
+            m[0][0] * m[1][3] - m[1][0] * m[0][3],
+            m[0][1] * m[1][2] - m[1][1] * m[0][2],
+            m[0][1] * m[1][3] - m[1][1] * m[0][3],
+            m[0][2] * m[1][3] - m[1][2] * m[0][3]
+        ];
+
+        let c = [
+            m[2][0] * m[3][1] - m[3][0] * m[2][1],
+            m[2][0] * m[3][2] - m[3][0] * m[2][2],
+            m[2][0] * m[3][3] - m[3][0] * m[2][3],
+            m[2][1] * m[3][2] - m[3][1] * m[2][2],
+            m[2][1] * m[3][3] - m[3][1] * m[2][3],
+            m[2][2] * m[3][3] - m[3][2] * m[2][3]
+        ];
+
+        (s, c)
+    }
+
+    pub fn determinant(&self) -> f32 {
+        let ([s0, s1, s2, s3, s4, s5], [c0, c1, c2, c3, c4, c5]) = self.sub_determinants();
+        s0 * c5 - s1 * c4 + s2 * c3 + s3 * c2 - s4 * c1 + s5 * c0
+    }
+
+    /// Returns the inverse of the matrix, or `None` if it is singular
+    pub fn inverse(&self) -> Option<Self> {
+        let determinant = self.determinant();
+        // Small scales have tiny determinants too, so only exactly singular matrices are rejected
+        if determinant == 0.0 || !(1.0 / determinant).is_finite() { return None; }
+
+        let m = &self.data;
+        let ([s0, s1, s2, s3, s4, s5], [c0, c1, c2, c3, c4, c5]) = self.sub_determinants();
+
+        let inv = 1.0 / determinant;
+
+        Some(Self::new([
+            [
+                ( m[1][1] * c5 - m[1][2] * c4 + m[1][3] * c3) * inv,
+                (-m[0][1] * c5 + m[0][2] * c4 - m[0][3] * c3) * inv,
+                ( m[3][1] * s5 - m[3][2] * s4 + m[3][3] * s3) * inv,
+                (-m[2][1] * s5 + m[2][2] * s4 - m[2][3] * s3) * inv
+            ],
+            [
+                (-m[1][0] * c5 + m[1][2] * c2 - m[1][3] * c1) * inv,
+                ( m[0][0] * c5 - m[0][2] * c2 + m[0][3] * c1) * inv,
+                (-m[3][0] * s5 + m[3][2] * s2 - m[3][3] * s1) * inv,
+                ( m[2][0] * s5 - m[2][2] * s2 + m[2][3] * s1) * inv
+            ],
+            [
+                ( m[1][0] * c4 - m[1][1] * c2 + m[1][3] * c0) * inv,
+                (-m[0][0] * c4 + m[0][1] * c2 - m[0][3] * c0) * inv,
+                ( m[3][0] * s4 - m[3][1] * s2 + m[3][3] * s0) * inv,
+                (-m[2][0] * s4 + m[2][1] * s2 - m[2][3] * s0) * inv
+            ],
+            [
+                (-m[1][0] * c3 + m[1][1] * c1 - m[1][2] * c0) * inv,
+                ( m[0][0] * c3 - m[0][1] * c1 + m[0][2] * c0) * inv,
+                (-m[3][0] * s3 + m[3][1] * s1 - m[3][2] * s0) * inv,
+                ( m[2][0] * s3 - m[2][1] * s1 + m[2][2] * s0) * inv
+            ]
+        ]))
+    }
+
+    /// Transform a position, translation included
+    pub fn transform_point(&self, point: &Vec3) -> Vec3 {
+        let m = &self.data;
+        let x = m[0][0] * point.x + m[0][1] * point.y + m[0][2] * point.z + m[0][3];
+        let y = m[1][0] * point.x + m[1][1] * point.y + m[1][2] * point.z + m[1][3];
+        let z = m[2][0] * point.x + m[2][1] * point.y + m[2][2] * point.z + m[2][3];
+        let w = m[3][0] * point.x + m[3][1] * point.y + m[3][2] * point.z + m[3][3];
+
+        if w != 0.0 && w != 1.0 {
+            return Vec3::new(x / w, y / w, z / w);
+        }
+
+        Vec3::new(x, y, z)
+    }
+
+    /// Returns the result of multiplying the matrix with the column vector `vector`
+    pub fn mul_vec4(&self, vector: &Vec4) -> Vec4 {
+        let m = &self.data;
+        let row = |i: usize| m[i][0] * vector.x + m[i][1] * vector.y + m[i][2] * vector.z + m[i][3] * vector.w;
+
+        Vec4::new(row(0), row(1), row(2), row(3))
+    }
+
+    /// Transform a direction, translation is ignored
+    pub fn transform_vector(&self, vector: &Vec3) -> Vec3 {
+        let m = &self.data;
+
+        Vec3::new(
+            m[0][0] * vector.x + m[0][1] * vector.y + m[0][2] * vector.z,
+            m[1][0] * vector.x + m[1][1] * vector.y + m[1][2] * vector.z,
+            m[2][0] * vector.x + m[2][1] * vector.y + m[2][2] * vector.z
+        )
+    }
+
+    /// The top left 3x3 part of the matrix, holding rotation and scale
+    pub fn to_matrix3(&self) -> Matrix3 {
+        let m = &self.data;
+
+        Matrix3::new([
+            [m[0][0], m[0][1], m[0][2]],
+            [m[1][0], m[1][1], m[1][2]],
+            [m[2][0], m[2][1], m[2][2]]
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{a} != {b}");
+    }
+
+    fn assert_vec3_close(a: Vec3, b: Vec3) {
+        assert_close(a.x, b.x);
+        assert_close(a.y, b.y);
+        assert_close(a.z, b.z);
+    }
+
+    fn assert_matrix4_close(a: &Matrix4, b: &Matrix4) {
+        for row in 0..4 {
+            for column in 0..4 {
+                assert_close(a.data[row][column], b.data[row][column]);
+            }
+        }
+    }
+
+    fn sample_matrix4() -> Matrix4 {
+        Matrix4::translation(Vec3::new(1.0, -2.0, 3.0))
+            .mul(&Matrix4::rotation(Vec3::new(1.0, 1.0, 0.0), 0.7))
+            .mul(&Matrix4::scaling(Vec3::new(2.0, 0.5, 3.0)))
+    }
+
+    #[test]
+    fn identity_is_neutral() {
+        let matrix = sample_matrix4();
+        assert_eq!(matrix.mul(&Matrix4::identity()), matrix);
+        assert_eq!(Matrix4::identity().mul(&matrix), matrix);
+
+        let vector = Vec3::new(1.0, 2.0, 3.0);
+        assert_vec3_close(Matrix3::identity().mul_vec3(&vector), vector);
+    }
+
+    #[test]
+    fn matrix3_multiplication() {
+        let a = Matrix3::new([[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [7.0, 8.0, 9.0]]);
+        let b = Matrix3::new([[9.0, 8.0, 7.0], [6.0, 5.0, 4.0], [3.0, 2.0, 1.0]]);
+
+        assert_eq!(a.mul(&b).data, [[30.0, 24.0, 18.0], [84.0, 69.0, 54.0], [138.0, 114.0, 90.0]]);
+        assert_vec3_close(a.mul_vec3(&Vec3::new(1.0, 0.0, -1.0)), Vec3::new(-2.0, -2.0, -2.0));
+    }
+
+    #[test]
+    fn transpose() {
+        let a = Matrix3::new([[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [7.0, 8.0, 9.0]]);
+        assert_eq!(a.transpose().data, [[1.0, 4.0, 7.0], [2.0, 5.0, 8.0], [3.0, 6.0, 9.0]]);
+
+        let b = sample_matrix4();
+        assert_eq!(b.transpose().transpose(), b);
+        assert_eq!(b.transpose().data[3][0], b.data[0][3]);
+    }
+
+    #[test]
+    fn determinant() {
+        let a = Matrix3::new([[2.0, 0.0, 1.0], [1.0, 3.0, 2.0], [1.0, 1.0, 2.0]]);
+        assert_close(a.determinant(), 6.0);
+
+        assert_close(Matrix4::scaling(Vec3::new(2.0, 3.0, 4.0)).determinant(), 24.0);
+        assert_close(Matrix4::rotation(Vec3::new(0.3, 1.0, -2.0), 1.2).determinant(), 1.0);
+        assert_close(sample_matrix4().determinant(), 3.0);
+    }
+
+    #[test]
+    fn inverse() {
+        let a = Matrix3::new([[2.0, 0.0, 1.0], [1.0, 3.0, 2.0], [1.0, 1.0, 2.0]]);
+        let product = a.mul(&a.inverse().unwrap());
+        assert_matrix4_close(&product.to_matrix4(), &Matrix4::identity());
+
+        let b = sample_matrix4();
+        assert_matrix4_close(&b.mul(&b.inverse().unwrap()), &Matrix4::identity());
+        assert_matrix4_close(&b.inverse().unwrap().mul(&b), &Matrix4::identity());
+
+        assert!(Matrix3::scaling(Vec3::new(1.0, 0.0, 1.0)).inverse().is_none());
+        assert!(Matrix4::scaling(Vec3::new(1.0, 1.0, 0.0)).inverse().is_none());
+    }
+
+    #[test]
+    fn inverts_small_scales() {
+        // A determinant of 6.4e-8 is still perfectly invertible
+        let scale = Vec3::new(0.004, 0.004, 0.004);
+        let m = Matrix4::scaling(scale).mul(&Matrix4::translation(Vec3::new(1.0, 2.0, 3.0)));
+        assert_matrix4_close(&m.mul(&m.inverse().unwrap()), &Matrix4::identity());
+
+        let inverse = Matrix3::scaling(scale).inverse().unwrap();
+        assert_close(inverse.data[0][0], 250.0);
+    }
+
+    #[test]
+    fn transforms() {
+        let point = Vec3::new(1.0, 2.0, 3.0);
+
+        let translated = Matrix4::translation(Vec3::new(1.0, 1.0, 1.0));
+        assert_vec3_close(translated.transform_point(&point), Vec3::new(2.0, 3.0, 4.0));
+        assert_vec3_close(translated.transform_vector(&point), point);
+
+        let scaled = Matrix4::scaling(Vec3::new(2.0, 3.0, -1.0));
+        assert_vec3_close(scaled.transform_point(&point), Vec3::new(2.0, 6.0, -3.0));
+
+        let rotated = Matrix4::rotation(Vec3::new(0.0, 0.0, 1.0), FRAC_PI_2);
+        assert_vec3_close(rotated.transform_point(&Vec3::new(1.0, 0.0, 0.0)), Vec3::new(0.0, 1.0, 0.0));
+
+        let rotated = Matrix3::rotation(Vec3::new(0.0, 1.0, 0.0), FRAC_PI_2);
+        assert_vec3_close(rotated.mul_vec3(&Vec3::new(0.0, 0.0, 1.0)), Vec3::new(1.0, 0.0, 0.0));
+
+        let vector = Matrix4::translation(Vec3::new(1.0, 1.0, 1.0)).mul_vec4(&Vec4::new(1.0, 2.0, 3.0, 1.0));
+        assert_close(vector.x, 2.0);
+        assert_close(vector.w, 1.0);
+    }
+
+    #[test]
+    fn perspective() {
+        let projection = Matrix4::perspective(FRAC_PI_2, 2.0, 1.0, 10.0);
+
+        // Points on the near and far planes land on the ends of the depth range
+        assert_close(projection.transform_point(&Vec3::new(0.0, 0.0, -1.0)).z, -1.0);
+        assert_close(projection.transform_point(&Vec3::new(0.0, 0.0, -10.0)).z, 1.0);
+
+        // With a 90 degree field of view the frustum edge is at 45 degrees
+        let edge = projection.transform_point(&Vec3::new(2.0, 1.0, -1.0));
+        assert_close(edge.x, 1.0);
+        assert_close(edge.y, 1.0);
+    }
+
+    #[test]
+    fn look_at() {
+        let eye = Vec3::new(0.0, 0.0, 5.0);
+        let view = Matrix4::look_at(eye, Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+
+        assert_vec3_close(view.transform_point(&eye), Vec3::new(0.0, 0.0, 0.0));
+        assert_vec3_close(view.transform_point(&Vec3::new(0.0, 0.0, 0.0)), Vec3::new(0.0, 0.0, -5.0));
+
+        let view = Matrix4::look_at(eye, Vec3::new(5.0, 0.0, 5.0), Vec3::new(0.0, 1.0, 0.0));
+        assert_vec3_close(view.transform_point(&Vec3::new(6.0, 1.0, 5.0)), Vec3::new(0.0, 1.0, -6.0));
+    }
+}
@@ -1,4 +1,4 @@
-use std::{ops::Range, time::SystemTime};
+use std::{cell::Cell, ops::Range, time::SystemTime};
 
 pub mod vec;
 pub mod matrix;
@@ -10,28 +10,43 @@ mod color;
 pub use color::Color;
 
 mod material;
-pub use material::Material;
+pub use material::{AlphaMode, Material, Subsurface};
+
+mod quat;
+pub use quat::Quat;
+
+mod aabb;
+pub use aabb::Aabb;
+
+mod spectrum;
+pub use spectrum::{Wavelengths, MIN_WAVELENGTH, MAX_WAVELENGTH, rgb_to_spectrum, get_color_matching, xyz_to_rgb};
 
 // In-house function for generating simple randomness without importing an entire crate
 /// Generates a random f32 between 0.0 - 1.0
 pub fn random() -> f32 {
-    // Create a seed from UNIX EPOCH until now in nanoseconds
-    let seed = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .expect("Time went backwards")
-        .as_nanos()
-        // Reversing the bits of the seed can help improve randomness.
-        // This is particularly useful when the seed is derived from time,
-        // as it helps to disperse the bits and avoid patterns related to
-        // the system's clock.
-        .reverse_bits();
-
-    // Reduce the size of the seed to avoid overflow
-    let seed = seed / 10e+28_f64 as u128;
-    // Divide by the closest maximum value depending on the amount of digits
-    let max = 10u128.pow(seed.to_string().len() as u32) - 1;
-
-    seed as f32 / max as f32
+    thread_local! {
+        // Seeded once per thread from UNIX EPOCH until now in nanoseconds,
+        // reading the clock on every call gives heavily correlated values
+        static STATE: Cell<u64> = Cell::new(
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_nanos() as u64
+                | 1
+        );
+    }
+
+    STATE.with(|state| {
+        // xorshift64*
+        let mut x = state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        state.set(x);
+
+        // The top 24 bits fit exactly in the mantissa, keeping the result below 1.0
+        (x.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 40) as f32 / (1u64 << 24) as f32
+    })
 }
 
 pub fn random_lcg() -> f32 {
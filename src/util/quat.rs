@@ -0,0 +1,210 @@
+use crate::util::{
+    matrix::{Matrix3, Matrix4},
+    vec::*
+};
+
+/// Quaternion `w + xi + yj + zk` representing a rotation when normalized
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quat {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32
+}
+
+impl Default for Quat {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Quat {
+    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Self { x, y, z, w }
+    }
+
+    /// Create a quaternion which doesn't rotate
+    pub fn identity() -> Self {
+        Self::new(0.0, 0.0, 0.0, 1.0)
+    }
+
+    /// Create a rotation of `angle` radians counter-clockwise around `axis`
+    pub fn from_axis_angle(axis: Vec3, angle: f32) -> Self {
+        let axis = axis.normalize();
+        let (sin, cos) = (angle / 2.0).sin_cos();
+
+        Self::new(axis.x * sin, axis.y * sin, axis.z * sin, cos)
+    }
+
+    /// Create a rotation from angles in radians around each axis,
+    /// applied around X first, then Y and finally Z
+    pub fn from_euler(x: f32, y: f32, z: f32) -> Self {
+        let rotation_x = Self::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), x);
+        let rotation_y = Self::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), y);
+        let rotation_z = Self::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), z);
+
+        rotation_z.mul(&rotation_y).mul(&rotation_x)
+    }
+
+    /// Create a quaternion from a pure rotation matrix
+    pub fn from_matrix3(matrix: &Matrix3) -> Self {
+        let m = &matrix.data;
+        let trace = m[0][0] + m[1][1] + m[2][2];
+
+        // Pick the largest diagonal term to divide by for numerical stability
+        let quat = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            Self::new((m[2][1] - m[1][2]) / s, (m[0][2] - m[2][0]) / s, (m[1][0] - m[0][1]) / s, s / 4.0)
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.0;
+            Self::new(s / 4.0, (m[0][1] + m[1][0]) / s, (m[0][2] + m[2][0]) / s, (m[2][1] - m[1][2]) / s)
+        } else if m[1][1] > m[2][2] {
+            let s = (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.0;
+            Self::new((m[0][1] + m[1][0]) / s, s / 4.0, (m[1][2] + m[2][1]) / s, (m[0][2] - m[2][0]) / s)
+        } else {
+            let s = (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.0;
+            Self::new((m[0][2] + m[2][0]) / s, (m[1][2] + m[2][1]) / s, s / 4.0, (m[1][0] - m[0][1]) / s)
+        };
+
+        quat.normalize()
+    }
+
+    /// Create a quaternion from the rotation part of a matrix without scale
+    pub fn from_matrix4(matrix: &Matrix4) -> Self {
+        Self::from_matrix3(&matrix.to_matrix3())
+    }
+
+    pub fn to_matrix3(&self) -> Matrix3 {
+        let Self { x, y, z, w } = self.normalize();
+
+        Matrix3::new([
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y)],
+            [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x)],
+            [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y)]
+        ])
+    }
+
+    pub fn to_matrix4(&self) -> Matrix4 {
+        self.to_matrix3().to_matrix4()
+    }
+
+    /// Returns the result of `self * other`, meaning `other` is applied first
+    pub fn mul(&self, other: &Self) -> Self {
+        Self::new(
+            self.w * other.x + self.x * other.w + self.y * other.z - self.z * other.y,
+            self.w * other.y - self.x * other.z + self.y * other.w + self.z * other.x,
+            self.w * other.z + self.x * other.y - self.y * other.x + self.z * other.w,
+            self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z
+        )
+    }
+
+    /// Rotate `vector` by the quaternion
+    pub fn rotate(&self, vector: &Vec3) -> Vec3 {
+        // Optimized form of q * v * q^-1 for unit quaternions
+        let axis = Vec3::new(self.x, self.y, self.z);
+        let t = axis.cross(vector).mul_by(2.0);
+
+        vector
+            .add(&t.mul_by(self.w))
+            .add(&axis.cross(&t))
+    }
+
+    /// The inverse rotation of a unit quaternion
+    pub fn conjugate(&self) -> Self {
+        Self::new(-self.x, -self.y, -self.z, self.w)
+    }
+
+    pub fn dot(&self, other: &Self) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w
+    }
+
+    pub fn magnitude(&self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalize(&self) -> Self {
+        let mag = self.magnitude();
+        if mag == 0.0 { return Self::identity(); }
+
+        Self::new(self.x / mag, self.y / mag, self.z / mag, self.w / mag)
+    }
+
+    /// Spherically interpolate between `self` and `other` along the shortest arc,
+    /// `t` goes from 0.0 (`self`) to 1.0 (`other`)
+    pub fn slerp(&self, other: &Self, t: f32) -> Self {
+        let mut other = *other;
+        let mut cos = self.dot(&other);
+
+        // `q` and `-q` are the same rotation, flip to take the shorter path
+        if cos < 0.0 {
+            other = Self::new(-other.x, -other.y, -other.z, -other.w);
+            cos = -cos;
+        }
+
+        // Nearly identical rotations would divide by ~0, lerp instead
+        let (a, b) = if cos > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let angle = cos.acos();
+            let sin = angle.sin();
+            (((1.0 - t) * angle).sin() / sin, (t * angle).sin() / sin)
+        };
+
+        Self::new(
+            self.x * a + other.x * b,
+            self.y * a + other.y * b,
+            self.z * a + other.z * b,
+            self.w * a + other.w * b
+        ).normalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::{FRAC_PI_2, PI};
+
+    fn assert_vec3_close(a: Vec3, b: Vec3) {
+        assert!(a.sub(&b).magnitude() < 1e-4, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn rotates_vectors() {
+        let quat = Quat::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), FRAC_PI_2);
+        assert_vec3_close(quat.rotate(&Vec3::new(1.0, 0.0, 0.0)), Vec3::new(0.0, 1.0, 0.0));
+        assert_vec3_close(quat.conjugate().rotate(&Vec3::new(0.0, 1.0, 0.0)), Vec3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn euler_order() {
+        // X first takes +Y to +Z, then Z leaves it alone
+        let quat = Quat::from_euler(FRAC_PI_2, 0.0, FRAC_PI_2);
+        assert_vec3_close(quat.rotate(&Vec3::new(0.0, 1.0, 0.0)), Vec3::new(0.0, 0.0, 1.0));
+        assert_vec3_close(quat.rotate(&Vec3::new(1.0, 0.0, 0.0)), Vec3::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn matches_matrix_rotation() {
+        let axis = Vec3::new(0.3, -1.0, 2.0);
+        let quat = Quat::from_axis_angle(axis, 2.5);
+        let matrix = Matrix3::rotation(axis, 2.5);
+        let vector = Vec3::new(1.0, 2.0, 3.0);
+
+        assert_vec3_close(quat.rotate(&vector), matrix.mul_vec3(&vector));
+        assert_vec3_close(quat.to_matrix3().mul_vec3(&vector), matrix.mul_vec3(&vector));
+
+        let roundtrip = Quat::from_matrix4(&quat.to_matrix4());
+        assert_vec3_close(roundtrip.rotate(&vector), quat.rotate(&vector));
+    }
+
+    #[test]
+    fn slerp() {
+        let start = Quat::identity();
+        let end = Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), PI * 0.75);
+        let halfway = start.slerp(&end, 0.5);
+        let expected = Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), PI * 0.375);
+
+        assert!((halfway.dot(&expected).abs() - 1.0).abs() < 1e-4);
+        assert!((start.slerp(&end, 1.0).dot(&end) - 1.0).abs() < 1e-4);
+    }
+}
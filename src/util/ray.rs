@@ -3,18 +3,45 @@ use crate::util::vec::*;
 #[derive(Clone, Copy)]
 pub struct Ray {
     pub position: Vec3,
-    pub direction: Vec3
+    pub direction: Vec3,
+    /// Width of the cone of space the ray stands for, at its origin
+    pub cone_width: f32,
+    /// How fast the cone widens, in radians
+    pub spread_angle: f32
 }
 
 impl Ray {
     pub fn new(position: Vec3, direction: Vec3) -> Self {
-        Self { position, direction }
+        Self { position, direction, cone_width: 0.0, spread_angle: 0.0 }
     }
 
-    pub fn get_point(&self, distance: f32) -> Vec2 {
-        Vec2 {
-            x: self.position.x + (self.direction.x * distance),
-            y: self.position.y + (self.direction.y * distance)
-        }
+    /// Makes the ray stand for a cone, so textures can be filtered over what it covers
+    pub fn set_cone(mut self, width: f32, spread_angle: f32) -> Self {
+        self.cone_width = width;
+        self.spread_angle = spread_angle;
+        self
+    }
+
+    /// Width of the cone after travelling `distance`, in multiples of the direction
+    pub fn get_cone_width(&self, distance: f32) -> f32 {
+        self.cone_width + self.spread_angle * distance * self.direction.magnitude()
+    }
+
+    pub fn get_point(&self, distance: f32) -> Vec3 {
+        self.direction
+            .mul_by(distance)
+            .add(&self.position)
+    }
+
+    /// `1.0 / direction` for slab tests against boxes. Axis parallel rays get a huge value
+    /// instead of infinity, as `0.0 * inf` would turn the test into NaN for rays grazing a box
+    pub fn get_inverse_direction(&self) -> Vec3 {
+        let inverse = |value: f32| if value == 0.0 { f32::MAX } else { 1.0 / value };
+
+        Vec3::new(
+            inverse(self.direction.x),
+            inverse(self.direction.y),
+            inverse(self.direction.z)
+        )
     }
 }
\ No newline at end of file
@@ -0,0 +1,189 @@
+use std::sync::OnceLock;
+
+use crate::util::{Color, random, vec::*};
+
+/// Shortest wavelength spectral rendering samples, in nanometres
+pub const MIN_WAVELENGTH: f32 = 380.0;
+/// Longest wavelength spectral rendering samples, in nanometres
+pub const MAX_WAVELENGTH: f32 = 720.0;
+
+/// Reflectance spectra RGB colors are built from (Smits 1999), in bins spread evenly over the visible range
+const SMITS_WHITE: [f32; 10] = [1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000];
+const SMITS_CYAN: [f32; 10] = [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000];
+const SMITS_MAGENTA: [f32; 10] = [1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959];
+const SMITS_YELLOW: [f32; 10] = [0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840];
+const SMITS_RED: [f32; 10] = [0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149];
+const SMITS_GREEN: [f32; 10] = [0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025];
+const SMITS_BLUE: [f32; 10] = [1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496];
+
+/// Wavelengths a path carries light at in spectral rendering, one per channel of the colors along it.
+/// The first is the hero wavelength, which dispersive surfaces keep when they split the others off
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Wavelengths {
+    /// In nanometres
+    pub wavelengths: [f32; 3]
+}
+
+impl Wavelengths {
+    /// Picks a random hero wavelength and spaces the others evenly after it, wrapping around the visible range
+    pub fn sample() -> Self {
+        Self::new(MIN_WAVELENGTH + random() * (MAX_WAVELENGTH - MIN_WAVELENGTH))
+    }
+
+    pub fn new(hero: f32) -> Self {
+        let range = MAX_WAVELENGTH - MIN_WAVELENGTH;
+        let offset = (hero - MIN_WAVELENGTH).rem_euclid(range);
+
+        Self {
+            wavelengths: [0.0, 1.0, 2.0].map(|i| MIN_WAVELENGTH + (offset + i * range / 3.0) % range)
+        }
+    }
+
+    pub fn get_hero(&self) -> f32 {
+        self.wavelengths[0]
+    }
+
+    /// Values of the spectrum of an RGB color at the wavelengths, packed into the channels of a color
+    pub fn from_rgb(&self, color: &Color) -> Color {
+        let [r, g, b] = self.wavelengths.map(|wavelength| rgb_to_spectrum(color, wavelength));
+        Color::rgb(r, g, b)
+    }
+
+    /// Linear RGB of light carried at the wavelengths, white balanced so a white surface stays white
+    pub fn to_rgb(&self, radiance: &Color) -> Color {
+        let channels = [radiance.r, radiance.g, radiance.b];
+        let pdf = 1.0 / (MAX_WAVELENGTH - MIN_WAVELENGTH);
+
+        let xyz = self.wavelengths
+            .iter()
+            .zip(channels)
+            .fold(Vec3::new(0.0, 0.0, 0.0), |xyz, (&wavelength, value)| {
+                xyz.add(&get_color_matching(wavelength).mul_by(value / pdf))
+            })
+            .div_by(3.0 * get_luminance_integral());
+
+        xyz_to_rgb(&xyz).div(&get_white_balance())
+    }
+}
+
+/// Smooth reflectance spectrum with the given RGB color, evaluated at `wavelength` nanometres.
+/// Scales with the color, so it also works for emission and other values above one
+pub fn rgb_to_spectrum(color: &Color, wavelength: f32) -> f32 {
+    let (r, g, b) = (color.r, color.g, color.b);
+    let bin = |spectrum: &[f32; 10]| {
+        let position = (wavelength - MIN_WAVELENGTH) / (MAX_WAVELENGTH - MIN_WAVELENGTH) * 9.0;
+        let index = (position.max(0.0) as usize).min(8);
+        let fraction = (position - index as f32).clamp(0.0, 1.0);
+
+        spectrum[index] * (1.0 - fraction) + spectrum[index + 1] * fraction
+    };
+
+    // White covers the smallest component, then the mixture of two primaries up to the middle one,
+    // then the largest primary by itself
+    let (white, middle, top, two, one) = if r <= g && r <= b {
+        if g <= b { (r, g, b, &SMITS_CYAN, &SMITS_BLUE) } else { (r, b, g, &SMITS_CYAN, &SMITS_GREEN) }
+    } else if g <= r && g <= b {
+        if r <= b { (g, r, b, &SMITS_MAGENTA, &SMITS_BLUE) } else { (g, b, r, &SMITS_MAGENTA, &SMITS_RED) }
+    } else if r <= g {
+        (b, r, g, &SMITS_YELLOW, &SMITS_GREEN)
+    } else {
+        (b, g, r, &SMITS_YELLOW, &SMITS_RED)
+    };
+
+    white * bin(&SMITS_WHITE) + (middle - white) * bin(two) + (top - middle) * bin(one)
+}
+
+/// CIE 1931 color matching functions at `wavelength` nanometres,
+/// from the multi-lobe fit of Wyman, Sloan and Shirley (2013)
+pub fn get_color_matching(wavelength: f32) -> Vec3 {
+    let lobe = |mean: f32, below: f32, above: f32| {
+        let spread = if wavelength < mean { below } else { above };
+        let t = (wavelength - mean) / spread;
+        (-0.5 * t * t).exp()
+    };
+
+    Vec3::new(
+        1.056 * lobe(599.8, 37.9, 31.0) + 0.362 * lobe(442.0, 16.0, 26.7) - 0.065 * lobe(501.1, 20.4, 26.2),
+        0.821 * lobe(568.8, 46.9, 40.5) + 0.286 * lobe(530.9, 16.3, 31.1),
+        1.217 * lobe(437.0, 11.8, 36.0) + 0.681 * lobe(459.0, 26.0, 13.8)
+    )
+}
+
+/// Linear sRGB of a CIE XYZ color
+pub fn xyz_to_rgb(xyz: &Vec3) -> Color {
+    Color::rgb(
+        3.240_454 * xyz.x - 1.537_138_5 * xyz.y - 0.498_531_4 * xyz.z,
+        -0.969_266 * xyz.x + 1.876_010_8 * xyz.y + 0.041_556 * xyz.z,
+        0.055_643_4 * xyz.x - 0.204_025_9 * xyz.y + 1.057_225_2 * xyz.z
+    )
+}
+
+/// Integral of the luminance matching function over the sampled range, so a constant spectrum of one has a luminance of one
+fn get_luminance_integral() -> f32 {
+    static INTEGRAL: OnceLock<f32> = OnceLock::new();
+
+    *INTEGRAL.get_or_init(|| {
+        (MIN_WAVELENGTH as usize..MAX_WAVELENGTH as usize)
+            .map(|wavelength| get_color_matching(wavelength as f32 + 0.5).y)
+            .sum()
+    })
+}
+
+/// RGB of the spectrum of white, which is divided out so white surfaces don't take on a tint
+fn get_white_balance() -> Color {
+    static WHITE: OnceLock<Color> = OnceLock::new();
+
+    *WHITE.get_or_init(|| {
+        let white = Color::rgb(1.0, 1.0, 1.0);
+        let xyz = (MIN_WAVELENGTH as usize..MAX_WAVELENGTH as usize)
+            .map(|wavelength| wavelength as f32 + 0.5)
+            .fold(Vec3::new(0.0, 0.0, 0.0), |xyz, wavelength| {
+                xyz.add(&get_color_matching(wavelength).mul_by(rgb_to_spectrum(&white, wavelength)))
+            })
+            .div_by(get_luminance_integral());
+
+        xyz_to_rgb(&xyz)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wavelengths_wrap_around() {
+        let wavelengths = Wavelengths::new(650.0);
+        assert_eq!(wavelengths.get_hero(), 650.0);
+
+        for wavelength in wavelengths.wavelengths {
+            assert!((MIN_WAVELENGTH..MAX_WAVELENGTH).contains(&wavelength));
+        }
+        assert!((wavelengths.wavelengths[1] - (650.0 + 340.0 / 3.0 - 340.0)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn colors_survive_the_round_trip() {
+        for color in [
+            Color::rgb(1.0, 1.0, 1.0),
+            Color::rgb(0.8, 0.1, 0.1),
+            Color::rgb(0.1, 0.6, 0.2),
+            Color::rgb(0.2, 0.3, 0.9),
+            Color::rgb(2.0, 2.0, 2.0)
+        ] {
+            // Averaging over many wavelengths, upsampling then converting back lands close to where it started
+            let count = 20000;
+            let mut average = Color::rgb(0.0, 0.0, 0.0);
+            for i in 0..count {
+                let hero = MIN_WAVELENGTH + (i as f32 + 0.5) / count as f32 * (MAX_WAVELENGTH - MIN_WAVELENGTH);
+                let wavelengths = Wavelengths::new(hero);
+                average.add_mut(&wavelengths.to_rgb(&wavelengths.from_rgb(&color)));
+            }
+            average = average.div_by(count as f32);
+
+            let tolerance = 0.1 * color.r.max(color.g).max(color.b);
+            assert!((average.r - color.r).abs() < tolerance, "{color:?} came back as {average:?}");
+            assert!((average.g - color.g).abs() < tolerance, "{color:?} came back as {average:?}");
+            assert!((average.b - color.b).abs() < tolerance, "{color:?} came back as {average:?}");
+        }
+    }
+}
\ No newline at end of file
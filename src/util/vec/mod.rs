@@ -140,7 +140,7 @@ pub trait Vector: Copy + Sized {
     }
 
     fn reflect(&mut self, normal: &Self) -> Self {
-        let dot = self.dot(&normal);
+        let dot = self.dot(normal);
         let normal_scaled = normal.mul_by(dot * 2.0);
         self.sub(&normal_scaled)
     }
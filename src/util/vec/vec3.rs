@@ -16,7 +16,7 @@ impl Vec3 {
     pub fn look_at(eye_level: Self, target: Self, up: Self) -> Matrix3 {
         // Calculate the forward, right, and up vectors
         let forward = target.sub(&eye_level).normalize();
-        let right = forward.cross(&up.normalize());
+        let right = forward.cross(&up).normalize();
         let up = right.cross(&forward);
 
         Matrix3::new([